@@ -0,0 +1,2 @@
+//! Re-exports the engine from a `dylib` for hot reload. See `game_systems` for how it's used.
+pub use engine::*;
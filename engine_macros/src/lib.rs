@@ -1,56 +1,658 @@
 extern crate proc_macro;
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, ItemFn, FnArg, Pat};
+use syn::{parse_macro_input, ItemFn, FnArg, Pat, Ident, LitStr, Type, PathArguments, GenericArgument};
+
+#[derive(Default)]
+struct SystemAttrs {
+    stage: Option<Ident>,
+    fixed: bool,
+    while_paused: bool,
+    parallel: Option<proc_macro2::Span>,
+    before: Vec<LitStr>,
+    after: Vec<LitStr>,
+    run_if: Option<syn::Path>,
+    state: Option<LitStr>,
+}
+
+impl SystemAttrs {
+    fn set_stage(&mut self, meta: &syn::meta::ParseNestedMeta, stage: Ident) -> syn::Result<()> {
+        if self.stage.is_some() {
+            return Err(meta.error("a system can only have one stage"));
+        }
+        self.stage = Some(stage);
+        Ok(())
+    }
+
+    fn set_state(&mut self, meta: &syn::meta::ParseNestedMeta, state: LitStr) -> syn::Result<()> {
+        if self.state.is_some() {
+            return Err(meta.error("a system can only be bound to one state"));
+        }
+        self.state = Some(state);
+        Ok(())
+    }
+
+    fn parse(&mut self, meta: syn::meta::ParseNestedMeta) -> syn::Result<()> {
+        let span = meta.path.segments.first().map(|s| s.ident.span()).unwrap_or_else(proc_macro2::Span::call_site);
+
+        if meta.path.is_ident("stage") {
+            let stage = meta.value()?.parse()?;
+            self.set_stage(&meta, stage)?;
+        } else if let Some(hook) = ["startup", "on_scene_loaded", "shutdown"].iter().find(|h| meta.path.is_ident(h)) {
+            let stage = match *hook {
+                "startup" => "Startup",
+                "on_scene_loaded" => "OnSceneLoaded",
+                _ => "Shutdown",
+            };
+            self.set_stage(&meta, Ident::new(stage, span))?;
+        } else if meta.path.is_ident("on_enter") || meta.path.is_ident("on_exit") {
+            let stage = if meta.path.is_ident("on_enter") { "OnEnter" } else { "OnExit" };
+            self.set_stage(&meta, Ident::new(stage, span))?;
+            let state = meta.value()?.parse()?;
+            self.set_state(&meta, state)?;
+        } else if meta.path.is_ident("state") {
+            let state = meta.value()?.parse()?;
+            self.set_state(&meta, state)?;
+        } else if meta.path.is_ident("fixed") {
+            self.fixed = true;
+        } else if meta.path.is_ident("while_paused") {
+            self.while_paused = true;
+        } else if meta.path.is_ident("parallel") {
+            self.parallel = Some(span);
+        } else if meta.path.is_ident("run_if") {
+            self.run_if = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("before") {
+            self.before.push(meta.value()?.parse()?);
+        } else if meta.path.is_ident("after") {
+            self.after.push(meta.value()?.parse()?);
+        } else {
+            return Err(meta.error(
+                "unknown system attribute, expected `stage`, `startup`, `on_scene_loaded`, `shutdown`, \
+                 `on_enter`, `on_exit`, `state`, `fixed`, `while_paused`, `parallel`, `run_if`, `before` or `after`"
+            ));
+        }
+        Ok(())
+    }
+}
+
+const CONTEXT_FIELDS: &[&str] = &["world", "sprites", "resources", "events", "commands", "dt", "alpha"];
+
+/// Splits `Wrapper<T>` into the wrapper name and `T` when the wrapper is one of `names`.
+fn generic_param<'a>(ty: &'a Type, names: &[&str]) -> Option<(String, &'a Type)> {
+    let Type::Path(type_path) = ty else { return None };
+    let segment = type_path.path.segments.last()?;
+    let wrapper = segment.ident.to_string();
+    if !names.contains(&wrapper.as_str()) {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else { return None };
+    args.args.iter().find_map(|arg| match arg {
+        GenericArgument::Type(inner) => Some((wrapper.clone(), inner)),
+        _ => None,
+    })
+}
+
+/// `&mut Commands` can be injected under any parameter name.
+fn is_commands(ty: &Type) -> bool {
+    let Type::Reference(reference) = ty else { return false };
+    let Type::Path(type_path) = &*reference.elem else { return false };
+    type_path.path.segments.last().is_some_and(|s| s.ident == "Commands")
+}
+
+/// Collects the components borrowed by a hecs query type such as `(&mut Vel, Option<&Player>)`.
+fn query_access(ty: &Type, out: &mut Vec<(Type, bool)>) {
+    match ty {
+        Type::Reference(reference) => out.push(((*reference.elem).clone(), reference.mutability.is_some())),
+        Type::Tuple(tuple) => tuple.elems.iter().for_each(|elem| query_access(elem, out)),
+        Type::Paren(paren) => query_access(&paren.elem, out),
+        Type::Path(type_path) => {
+            // `Or<L, R>` borrows both sides
+            if let Some(segment) = type_path.path.segments.last().filter(|segment| segment.ident == "Or") {
+                if let PathArguments::AngleBracketed(args) = &segment.arguments {
+                    for arg in &args.args {
+                        if let GenericArgument::Type(inner) = arg {
+                            query_access(inner, out);
+                        }
+                    }
+                }
+            // `With<Q, R>` / `Without<Q, R>` only borrow `Q`, so the first argument is enough
+            } else if let Some((_, inner)) = generic_param(ty, &["Option", "With", "Without"]) {
+                query_access(inner, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn access_entry(ty: &Type, write: bool) -> proc_macro2::TokenStream {
+    quote! {
+        crate::systems::Access { name: stringify!(#ty), type_id: std::any::TypeId::of::<#ty>, write: #write }
+    }
+}
+
+#[derive(Default)]
+struct SystemParams {
+    /// Runs before any parameter is borrowed, while `ctx` is still freely mutable.
+    setup: proc_macro2::TokenStream,
+    unpacking: proc_macro2::TokenStream,
+    idents: Vec<Ident>,
+    access: Vec<proc_macro2::TokenStream>,
+    exclusive: bool,
+    /// Needs `&mut` context (sprites, commands or anything exclusive), so it can't run on a worker thread.
+    needs_mut: bool,
+    world: Option<Ident>,
+    query: Option<Ident>,
+}
+
+impl SystemParams {
+    fn add(&mut self, arg_name: &Ident, ty: &Type) -> syn::Result<()> {
+        self.idents.push(arg_name.clone());
+
+        if let Some((wrapper, inner)) = generic_param(ty, &["Res", "ResMut"]) {
+            let is_mut = wrapper == "ResMut";
+            self.access.push(access_entry(inner, is_mut));
+            self.unpacking.extend(if is_mut {
+                quote! { let #arg_name = ctx.resources.expect_mut::<#inner>(); }
+            } else {
+                quote! { let #arg_name = ctx.resources.expect::<#inner>(); }
+            });
+            return Ok(());
+        }
+
+        if let Some((wrapper, inner)) = generic_param(ty, &["EventWriter", "EventReader"]) {
+            let is_writer = wrapper == "EventWriter";
+            self.access.push(access_entry(&syn::parse_quote!(crate::events::Channel<#inner>), is_writer));
+            self.setup.extend(quote! { events.register::<#inner>(); });
+            self.unpacking.extend(if is_writer {
+                quote! { let #arg_name = ctx.events.writer::<#inner>(); }
+            } else {
+                quote! { let #arg_name = ctx.events.reader::<#inner>(); }
+            });
+            return Ok(());
+        }
+
+        if let Some((_, inner)) = generic_param(ty, &["Query"]) {
+            let mut borrows = Vec::new();
+            query_access(inner, &mut borrows);
+            self.access.extend(borrows.iter().map(|(component, write)| access_entry(component, *write)));
+            self.unpacking.extend(quote! { let #arg_name = crate::systems::Query::new(&*ctx.world); });
+            self.query.get_or_insert_with(|| arg_name.clone());
+            return Ok(());
+        }
+
+        if is_commands(ty) {
+            self.access.push(access_entry(&syn::parse_quote!(crate::systems::Commands), true));
+            self.unpacking.extend(quote! { let #arg_name = &mut *ctx.commands; });
+            self.needs_mut = true;
+            return Ok(());
+        }
+
+        match arg_name.to_string().as_str() {
+            "dt" | "alpha" => {
+                self.unpacking.extend(quote! { let #arg_name = ctx.#arg_name; });
+            }
+            "sprites" => {
+                self.access.push(access_entry(&syn::parse_quote!(crate::sprite_manager::SpriteManager), true));
+                self.unpacking.extend(quote! { let #arg_name = &mut *ctx.#arg_name; });
+                self.needs_mut = true;
+            }
+            name if CONTEXT_FIELDS.contains(&name) => {
+                self.exclusive = true;
+                self.needs_mut = true;
+                if name == "world" {
+                    self.world = Some(arg_name.clone());
+                }
+                self.unpacking.extend(quote! { let #arg_name = &mut *ctx.#arg_name; });
+            }
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    ty,
+                    format!(
+                        "`{}` is not a system parameter: expected a context field ({}), a resource (`Res<T>` / `ResMut<T>`), an event channel (`EventReader<T>` / `EventWriter<T>`), `&mut Commands` or a `Query<Q>`",
+                        arg_name, CONTEXT_FIELDS.join(", ")
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
 
 #[proc_macro_attribute]
-pub fn system(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn system(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut attrs = SystemAttrs::default();
+    let attr_parser = syn::meta::parser(|meta| attrs.parse(meta));
+    parse_macro_input!(attr with attr_parser);
+
     let input = parse_macro_input!(item as ItemFn);
     let name = &input.sig.ident;
     let vis = &input.vis;
     //let body = &input.block;
-    
+
     let wrapper_name = quote::format_ident!("{}_wrapper", name);
+    let shared_name = quote::format_ident!("{}_shared", name);
+    let setup_name = quote::format_ident!("{}_setup", name);
 
-    let mut args_unpacking = quote! {};
+    let stage = attrs.stage.unwrap_or_else(|| quote::format_ident!("Update"));
+    let fixed = attrs.fixed;
+    let while_paused = attrs.while_paused;
+    let before = &attrs.before;
+    let after = &attrs.after;
+    let run_if = match &attrs.run_if {
+        Some(path) => quote! { Some(#path) },
+        None => quote! { None },
+    };
+    let state = match &attrs.state {
+        Some(state) => quote! { Some(#state) },
+        None => quote! { None },
+    };
+
+    let mut params = SystemParams::default();
     for arg in &input.sig.inputs {
-        if let FnArg::Typed(pat_type) = arg {
-            if let Pat::Ident(pat_ident) = &*pat_type.pat {
-                let arg_name = &pat_ident.ident;
-                args_unpacking.extend(quote! {
-                    let #arg_name = &mut *ctx.#arg_name;
-                });
-            }
+        if let FnArg::Typed(pat_type) = arg
+            && let Pat::Ident(pat_ident) = &*pat_type.pat
+            && let Err(err) = params.add(&pat_ident.ident, &pat_type.ty)
+        {
+            return err.to_compile_error().into();
         }
     }
-    let arg_idents: Vec<syn::Ident> = input.sig.inputs.iter().filter_map(|arg| {
-        if let FnArg::Typed(pat_type) = arg {
-            if let Pat::Ident(pat_ident) = &*pat_type.pat {
-                Some(pat_ident.ident.clone())
-            } else {
-                None
-            }
-        } else {
-            None
+    if let (Some(world), Some(query)) = (&params.world, &params.query) {
+        let msg = format!("`{}` borrows the whole world, so it can't be combined with the query `{}`; use one or the other", world, query);
+        return syn::Error::new_spanned(world, msg).to_compile_error().into();
+    }
+
+    if let Some(span) = attrs.parallel {
+        if params.needs_mut {
+            let msg = "a `parallel` system can only take shared borrows: `Res`, `EventReader`/`EventWriter`, `Query`, `dt` and `alpha`";
+            return syn::Error::new(span, msg).to_compile_error().into();
         }
-    }).collect();
+        if stage == "Render" {
+            return syn::Error::new(span, "Render systems draw through macroquad, which only works on the main thread, so they can't be `parallel`").to_compile_error().into();
+        }
+    }
+
+    let setup = &params.setup;
+    let args_unpacking = &params.unpacking;
+    let arg_idents = &params.idents;
+    let access = &params.access;
+    let exclusive = params.exclusive;
+
+    // `parallel` systems get a second entry point for worker threads; the unpacking code is
+    // the same because `SharedCtx` mirrors the shareable `SysCtx` fields. It's opt-in because
+    // macroquad's input, time and drawing functions panic off the main thread.
+    let (wrappers, par_func) = if attrs.parallel.is_none() {
+        (quote! {
+            fn #wrapper_name(ctx: &mut crate::systems::SysCtx) {
+                #setup_name(ctx.events);
+                #args_unpacking
+                #name( #(#arg_idents),* );
+            }
+        }, quote! { None })
+    } else {
+        (quote! {
+            fn #shared_name(ctx: &crate::systems::SharedCtx) {
+                #args_unpacking
+                #name( #(#arg_idents),* );
+            }
+
+            fn #wrapper_name(ctx: &mut crate::systems::SysCtx) {
+                #setup_name(ctx.events);
+                #shared_name(&ctx.shared());
+            }
+        }, quote! { Some(#shared_name) })
+    };
 
     let expanded = quote! {
 
         #vis #input
 
-        fn #wrapper_name(ctx: &mut crate::systems::SysCtx) {
-            #args_unpacking
-            #name( #(#arg_idents),* );
+        #[allow(unused_variables)]
+        fn #setup_name(events: &mut crate::events::Events) {
+            #setup
         }
 
-        inventory::submit! {
-            crate::systems::GameSystem { 
+        #wrappers
+
+        crate::systems::register_system! {
+            crate::systems::GameSystem {
                 name: stringify!(#name),
-                func: #wrapper_name 
+                func: #wrapper_name,
+                par_func: #par_func,
+                setup: #setup_name,
+                stage: crate::systems::Stage::#stage,
+                fixed: #fixed,
+                while_paused: #while_paused,
+                run_if: #run_if,
+                state: #state,
+                before: &[ #(#before),* ],
+                after: &[ #(#after),* ],
+                access: &[ #(#access),* ],
+                exclusive: #exclusive,
+                script: None,
             }
         }
     };
 
     TokenStream::from(expanded)
-}
\ No newline at end of file
+}
+
+/// Reads the inspector attributes of a field into a `crate::reflect::FieldHints` expression.
+fn field_hints(attrs: &[syn::Attribute]) -> syn::Result<proc_macro2::TokenStream> {
+    let mut range = quote! { None };
+    let mut step = quote! { None };
+    let mut tooltip = quote! { None };
+    let (mut slider, mut hidden, mut readonly) = (false, false, false);
+
+    for attr in attrs {
+        let path = attr.path();
+        if path.is_ident("range") {
+            let bounds = attr.parse_args_with(syn::punctuated::Punctuated::<syn::Expr, syn::Token![,]>::parse_terminated)?;
+            let (2, Some(min), Some(max)) = (bounds.len(), bounds.first(), bounds.last()) else {
+                return Err(syn::Error::new_spanned(attr, "expected `#[range(min, max)]`"));
+            };
+            range = quote! { Some(((#min) as f64, (#max) as f64)) };
+        } else if path.is_ident("step") {
+            let value: syn::Expr = attr.parse_args()?;
+            step = quote! { Some((#value) as f64) };
+        } else if path.is_ident("tooltip") {
+            let text: LitStr = attr.parse_args()?;
+            tooltip = quote! { Some(#text) };
+        } else if path.is_ident("slider") {
+            attr.meta.require_path_only()?;
+            slider = true;
+        } else if path.is_ident("hidden") {
+            attr.meta.require_path_only()?;
+            hidden = true;
+        } else if path.is_ident("readonly") {
+            attr.meta.require_path_only()?;
+            readonly = true;
+        }
+    }
+    Ok(quote! {
+        crate::reflect::FieldHints { range: #range, slider: #slider, step: #step, tooltip: #tooltip, hidden: #hidden, readonly: #readonly }
+    })
+}
+
+/// One `field_row` call per field; `access` is how to reach each field (`&mut self.x` or a binding).
+fn field_rows(fields: &syn::Fields, access: impl Fn(usize, &syn::Field) -> proc_macro2::TokenStream) -> syn::Result<proc_macro2::TokenStream> {
+    let mut rows = proc_macro2::TokenStream::new();
+    for (i, field) in fields.iter().enumerate() {
+        let name = field.ident.as_ref().map(|ident| ident.to_string()).unwrap_or_else(|| i.to_string());
+        let ty = &field.ty;
+        let hints = field_hints(&field.attrs)?;
+        let value = access(i, field);
+        rows.extend(quote! {
+            changed |= crate::inspector::field_row(
+                ui, ctx, #name, &#hints, (&crate::inspector::Probe::<#ty>::new()).widget(), #value, stringify!(#ty),
+            );
+        });
+    }
+    Ok(rows)
+}
+
+fn variant_bindings(fields: &syn::Fields) -> (proc_macro2::TokenStream, Vec<Ident>) {
+    let names: Vec<Ident> = (0..fields.len()).map(|i| quote::format_ident!("field_{}", i)).collect();
+    let pattern = match fields {
+        syn::Fields::Named(named) => {
+            let idents = named.named.iter().map(|f| f.ident.as_ref().unwrap());
+            quote! { { #(#idents: #names),* } }
+        }
+        syn::Fields::Unnamed(_) => quote! { ( #(#names),* ) },
+        syn::Fields::Unit => quote! {},
+    };
+    (pattern, names)
+}
+
+fn inspect_body(input: &syn::DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+    match &input.data {
+        syn::Data::Struct(data) => {
+            let rows = field_rows(&data.fields, |i, field| match &field.ident {
+                Some(ident) => quote! { &mut self.#ident },
+                None => {
+                    let index = syn::Index::from(i);
+                    quote! { &mut self.#index }
+                }
+            })?;
+            Ok(quote! {
+                crate::inspector::group(ui, ctx, stringify!(#name), |ui, ctx| {
+                    let mut changed = false;
+                    #rows
+                    changed
+                })
+            })
+        }
+        syn::Data::Enum(data) => {
+            let variant_names: Vec<String> = data.variants.iter().map(|v| v.ident.to_string()).collect();
+            let mut current_arms = Vec::new();
+            let mut default_arms = Vec::new();
+            let mut field_arms = Vec::new();
+
+            for (i, variant) in data.variants.iter().enumerate() {
+                let ident = &variant.ident;
+                let (pattern, bindings) = variant_bindings(&variant.fields);
+                current_arms.push(quote! { #name::#ident { .. } => #i });
+
+                let defaults = variant.fields.iter().map(|_| quote! { Default::default() });
+                default_arms.push(match &variant.fields {
+                    syn::Fields::Named(named) => {
+                        let idents = named.named.iter().map(|f| f.ident.as_ref().unwrap());
+                        quote! { #i => #name::#ident { #(#idents: #defaults),* } }
+                    }
+                    syn::Fields::Unnamed(_) => quote! { #i => #name::#ident( #(#defaults),* ) },
+                    syn::Fields::Unit => quote! { #i => #name::#ident },
+                });
+
+                if variant.fields.is_empty() {
+                    field_arms.push(quote! { #name::#ident => {} });
+                } else {
+                    let rows = field_rows(&variant.fields, |i, _| {
+                        let binding = &bindings[i];
+                        quote! { #binding }
+                    })?;
+                    field_arms.push(quote! {
+                        #name::#ident #pattern => {
+                            changed |= crate::inspector::group(ui, ctx, stringify!(#ident), |ui, ctx| {
+                                let mut changed = false;
+                                #rows
+                                changed
+                            });
+                        }
+                    });
+                }
+            }
+
+            Ok(quote! {
+                ui.vertical(|ui| {
+                    const VARIANTS: &[&str] = &[ #(#variant_names),* ];
+                    let current = match self { #(#current_arms),* };
+                    let mut changed = false;
+                    if let Some(selected) = crate::inspector::variant_picker(ui, ctx, VARIANTS, current) {
+                        *self = match selected {
+                            #(#default_arms,)*
+                            _ => unreachable!(),
+                        };
+                        changed = true;
+                    }
+                    match self {
+                        #(#field_arms)*
+                    }
+                    changed
+                }).inner
+            })
+        }
+        syn::Data::Union(_) => Err(syn::Error::new_spanned(name, "`Inspect` can't be derived for unions")),
+    }
+}
+
+/// Makes a plain struct or enum editable in the inspector: structs become a collapsible group of
+/// their fields, enums a dropdown of their variants plus the fields of the selected one.
+/// Fields take the same attributes as component fields (`#[range]`, `#[slider]`, `#[step]`,
+/// `#[tooltip]`, `#[hidden]`, `#[readonly]`); variant fields start from `Default` when switching.
+#[proc_macro_derive(Inspect, attributes(range, slider, step, tooltip, hidden, readonly))]
+pub fn inspect(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as syn::DeriveInput);
+    let body = match inspect_body(&input) {
+        Ok(body) => body,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    TokenStream::from(quote! {
+        #[cfg(debug_assertions)]
+        impl #impl_generics crate::inspector::Inspect for #name #ty_generics #where_clause {
+            #[allow(unused_mut, unused_variables)]
+            fn inspect(&mut self, ui: &mut crate::inspector::egui::Ui, ctx: &crate::inspector::WidgetCtx) -> bool {
+                #[allow(unused_imports)]
+                use crate::inspector::{ViaInspect as _, ViaFallback as _};
+                #body
+            }
+        }
+    })
+}
+
+/// Turns a struct with named fields into a component: derives `Clone`, serde and `Inspect`,
+/// implements `Default` from the fields' `#[default(expr)]` attributes (`Default::default()`
+/// otherwise) and registers its `crate::reflect::ComponentInfo`, so scenes, duplication, scripts
+/// and the inspector pick it up wherever it is declared. Fields take the inspector attributes of `Inspect`.
+/// `#[component]` or `#[component(version = 2)]`. Bump the version together with a
+/// `#[migration]` whenever saved scenes need converting.
+#[proc_macro_attribute]
+pub fn component(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut version = None;
+    let attr_parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("version") {
+            version = Some(meta.value()?.parse::<syn::LitInt>()?);
+            Ok(())
+        } else {
+            Err(meta.error("expected `version = N`"))
+        }
+    });
+    parse_macro_input!(attr with attr_parser);
+
+    let mut input = parse_macro_input!(item as syn::ItemStruct);
+    match component_impl(&mut input, version) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn component_impl(input: &mut syn::ItemStruct, version: Option<syn::LitInt>) -> syn::Result<proc_macro2::TokenStream> {
+    let name = input.ident.clone();
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(&input.generics, "components can't be generic"));
+    }
+    let syn::Fields::Named(fields) = &mut input.fields else {
+        return Err(syn::Error::new_spanned(&input.ident, "components need named fields"));
+    };
+
+    let version_expr = match &version {
+        Some(version) => quote! { #version },
+        None => quote! { 0 },
+    };
+    let mut defaults = Vec::new();
+    let mut field_infos = Vec::new();
+    for field in fields.named.iter_mut() {
+        let mut default = quote! { Default::default() };
+        let mut attr_error = None;
+        field.attrs.retain(|attr| {
+            if !attr.path().is_ident("default") {
+                return true;
+            }
+            match attr.parse_args::<syn::Expr>() {
+                Ok(expr) => default = quote! { #expr },
+                Err(err) => attr_error = Some(err),
+            }
+            false
+        });
+        if let Some(err) = attr_error {
+            return Err(err);
+        }
+
+        // Fields missing from a saved scene get their type's default, as they always have
+        field.attrs.push(syn::parse_quote! { #[serde(default)] });
+
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let hints = field_hints(&field.attrs)?;
+        defaults.push(quote! { #ident: #default });
+        field_infos.push(quote! {
+            crate::reflect::FieldInfo {
+                name: stringify!(#ident),
+                type_name: stringify!(#ty),
+                type_id: std::any::TypeId::of::<#ty>,
+                offset: std::mem::offset_of!(#name, #ident),
+                default: || {
+                    let value: #ty = #default;
+                    Box::new(value)
+                },
+                get: |comp| &comp.downcast_ref::<#name>().unwrap().#ident,
+                get_mut: |comp| &mut comp.downcast_mut::<#name>().unwrap().#ident,
+                reset: |comp| comp.downcast_mut::<#name>().unwrap().#ident = #default,
+                #[cfg(debug_assertions)]
+                inspect: || {
+                    #[allow(unused_imports)]
+                    use crate::inspector::{ViaInspect as _, ViaFallback as _};
+                    (&crate::inspector::Probe::<#ty>::new()).widget()
+                },
+                hints: #hints,
+            }
+        });
+    }
+
+    Ok(quote! {
+        #[derive(Clone, serde::Serialize, serde::Deserialize, engine_macros::Inspect)]
+        #[serde(default)]
+        #input
+
+        impl Default for #name {
+            fn default() -> Self {
+                Self { #(#defaults),* }
+            }
+        }
+
+        crate::reflect::register_component! {
+            crate::reflect::ComponentInfo::of::<#name>(stringify!(#name), &[ #(#field_infos),* ]).with_version(#version_expr)
+        }
+    })
+}
+
+/// `#[migration(Collider, from = 0)] fn collider_size(m: &mut Migrate)` upgrades saved
+/// `Collider`s from version 0 to 1. The component is named, not referenced, so migrations
+/// for components that were since renamed or removed still compile.
+#[proc_macro_attribute]
+pub fn migration(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut component = None;
+    let mut from = None;
+    let attr_parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("from") {
+            from = Some(meta.value()?.parse::<syn::LitInt>()?);
+        } else if component.is_none() && let Some(ident) = meta.path.get_ident() {
+            component = Some(ident.clone());
+        } else {
+            return Err(meta.error("expected `Component, from = N`"));
+        }
+        Ok(())
+    });
+    parse_macro_input!(attr with attr_parser);
+
+    let input = parse_macro_input!(item as ItemFn);
+    let (Some(component), Some(from)) = (component, from) else {
+        return syn::Error::new_spanned(&input.sig.ident, "expected `#[migration(Component, from = N)]`").to_compile_error().into();
+    };
+    let name = &input.sig.ident;
+
+    TokenStream::from(quote! {
+        #input
+
+        crate::migrate::register_migration! {
+            crate::migrate::Migration {
+                component: stringify!(#component),
+                from: #from,
+                name: stringify!(#name),
+                apply: #name,
+            }
+        }
+    })
+}
@@ -0,0 +1,24 @@
+use std::fs;
+use std::path::Path;
+
+// Mirrors `automod::dir!("src/systems")` in the engine, so both builds pick up the same files.
+fn main() {
+    let systems_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../src/systems");
+    println!("cargo:rerun-if-changed={}", systems_dir.display());
+
+    let mut modules = String::new();
+    let mut entries: Vec<_> = fs::read_dir(&systems_dir).unwrap().flatten().map(|e| e.path()).collect();
+    entries.sort();
+
+    for path in entries {
+        if path.extension().and_then(|s| s.to_str()) != Some("rs") {
+            continue;
+        }
+        let name = path.file_stem().unwrap().to_str().unwrap();
+        let path = path.canonicalize().unwrap();
+        modules.push_str(&format!("#[path = {:?}]\nmod {};\n", path.display().to_string(), name));
+    }
+
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    fs::write(Path::new(&out_dir).join("systems.rs"), modules).unwrap();
+}
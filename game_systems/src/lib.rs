@@ -0,0 +1,62 @@
+//! `src/systems/` built as a dynamic library for the editor's hot-reload mode.
+//! The editor (`cargo run -p en_dylib --features hot-reload`) loads it on startup and again every
+//! time `cargo build -p game_systems --features en/hot-reload` replaces it.
+//!
+//! Both commands need `RUSTFLAGS="-C prefer-dynamic"`, so the editor and this library share
+//! the `en_dylib` engine (and with it macroquad's input state and the component type ids).
+
+// `#[system]` expands to `crate::...` paths, so the engine modules it needs are mirrored here
+pub use ::en::{en, events, prefab, resources, scene_manager, scripting, sprite_manager};
+#[cfg(debug_assertions)]
+pub use ::en::inspector;
+
+/// Components stay with the engine: a `#[component]` declared under `src/systems` would be
+/// a separate type in this library, so with hot reload components must live elsewhere.
+pub mod reflect {
+    pub use ::en::reflect::*;
+
+    #[allow(unused_macros)]
+    macro_rules! register_component {
+        ($info:expr) => {};
+    }
+    #[allow(unused_imports)]
+    pub(crate) use register_component;
+}
+
+/// Migrations belong next to their components, so this library doesn't register them either.
+pub mod migrate {
+    pub use ::en::migrate::*;
+
+    #[allow(unused_macros)]
+    macro_rules! register_migration {
+        ($migration:expr) => {};
+    }
+    #[allow(unused_imports)]
+    pub(crate) use register_migration;
+}
+
+pub mod systems {
+    pub use ::en::systems::*;
+
+    /// Systems of this library, kept apart from the engine's own `GameSystem` registry
+    /// so a reload doesn't leave stale entries behind.
+    pub struct HotSystem(pub GameSystem);
+
+    inventory::collect!(HotSystem);
+
+    macro_rules! register_system {
+        ($system:expr) => {
+            inventory::submit! { crate::systems::HotSystem($system) }
+        };
+    }
+    pub(crate) use register_system;
+
+    include!(concat!(env!("OUT_DIR"), "/systems.rs"));
+}
+
+/// Entry point looked up by `en::hot_reload`. Both sides are built by the same compiler
+/// against the same `en` dylib, so passing Rust types across is fine.
+#[unsafe(no_mangle)]
+pub fn en_hot_systems() -> Vec<&'static ::en::systems::GameSystem> {
+    inventory::iter::<systems::HotSystem>.into_iter().map(|s| &s.0).collect()
+}
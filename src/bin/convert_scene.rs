@@ -0,0 +1,33 @@
+use std::env;
+use std::path::Path;
+
+/// Converts a scene between the binary and text formats, picked by extension:
+/// `cargo run --bin convert_scene Scene.bin Scene.json`. With one argument the
+/// other format is written next to it.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let (from, to) = match args.as_slice() {
+        [_, from, to] => (Path::new(from).to_path_buf(), Path::new(to).to_path_buf()),
+        [_, from] => {
+            let from = Path::new(from).to_path_buf();
+            let ext = match en::scene::SceneFormat::from_path(&from) {
+                en::scene::SceneFormat::Binary => "json",
+                en::scene::SceneFormat::Text => "bin",
+            };
+            let to = from.with_extension(ext);
+            (from, to)
+        }
+        _ => {
+            eprintln!("Usage: convert_scene <input> [output]");
+            std::process::exit(1);
+        }
+    };
+
+    match en::scene::convert_scene_file(&from, &to) {
+        Ok(()) => println!("Wrote {}", to.display()),
+        Err(e) => {
+            eprintln!("Failed to convert scene: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
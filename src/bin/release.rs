@@ -39,13 +39,13 @@ fn main() {
         return;
     }
 
-    if Path::new("Scene.bin").exists() {
-        fs::copy("Scene.bin", format!("{}/Scene.bin", target_dir)).unwrap();
-    }
-
     println!("Copying assets...");
     copy_assets(Path::new("assets"), Path::new(&format!("{}/assets", target_dir))).unwrap();
 
+    if let Err(e) = en::scene_manager::ship_scenes(Path::new(&target_dir)) {
+        eprintln!("Failed to export scene: {}", e);
+    }
+
     println!("Done! Your game is ready to ship in: {}", target_dir);
 }
 
@@ -64,4 +64,4 @@ fn copy_assets(src: &Path, dst: &Path) -> std::io::Result<()> {
         }
     }
     Ok(())
-}
\ No newline at end of file
+}
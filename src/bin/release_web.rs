@@ -55,8 +55,8 @@ fn main() {
 
     copy_dir_recursive(Path::new("assets"), &build_dir.join("assets")).unwrap();
 
-    if Path::new("Scene.bin").exists() {
-        fs::copy("Scene.bin", build_dir.join("Scene.bin")).unwrap();
+    if let Err(e) = en::scene_manager::ship_scenes(&build_dir) {
+        eprintln!("Failed to export scene: {}", e);
     }
 
     println!("✅ Web build ready in {}!", build_dir.display());
@@ -76,4 +76,4 @@ fn copy_dir_recursive(src: &Path, dst: &Path) -> std::io::Result<()> {
         }
     }
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,68 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use en::scene::{Scene, SceneError, SceneFormat};
+
+/// Migrates every scene file (`.bin` and `.json`) in a directory to the current component
+/// versions, in place: `cargo run --bin upgrade_scenes [dir]` (e.g. `assets/scenes`; the project
+/// root by default). Files that don't read as scenes are skipped; files that would no longer
+/// load are reported and left alone.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let dir = args.get(1).map(String::as_str).unwrap_or(".");
+
+    let mut entries: Vec<_> = match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect(),
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", dir, e);
+            std::process::exit(1);
+        }
+    };
+    entries.sort();
+
+    let mut failed = false;
+    for path in entries {
+        if !matches!(path.extension().and_then(|ext| ext.to_str()), Some("bin" | "json")) {
+            continue;
+        }
+        if let Err(e) = upgrade_file(&path) {
+            eprintln!("{}: {}", path.display(), e);
+            failed = true;
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+fn upgrade_file(path: &Path) -> Result<(), String> {
+    let data = fs::read(path).map_err(|e| e.to_string())?;
+    let format = SceneFormat::from_path(path);
+    let mut scene = match Scene::decode(&data, format) {
+        Ok(scene) => scene,
+        Err(e @ SceneError::Newer { .. }) => return Err(e.to_string()),
+        Err(e) => {
+            println!("{}: skipped, {}", path.display(), e);
+            return Ok(());
+        }
+    };
+
+    let report = en::migrate::upgrade(&mut scene).map_err(|e| e.to_string())?;
+    scene.clone().spawn_into(&mut hecs::World::new()).map_err(|e| format!("not upgraded, {}", e))?;
+
+    let upgraded = scene.encode(format);
+    if upgraded == data {
+        println!("{}: up to date", path.display());
+        return Ok(());
+    }
+    fs::write(path, upgraded).map_err(|e| e.to_string())?;
+    if report.is_empty() {
+        println!("{}: rewritten in the current file layout", path.display());
+    } else {
+        println!("{}: upgraded", path.display());
+        for line in report.to_string().lines() {
+            println!("    {}", line);
+        }
+    }
+    Ok(())
+}
@@ -1,40 +1,105 @@
 use macroquad::{prelude::*};
+use engine_macros::{component, migration};
+use crate::migrate::Migrate;
 use crate::render::PPU;
 
-crate::define_all_components! {
-    Pos { x: f32, y: f32 },
+#[component]
+pub struct Pos { pub x: f32, pub y: f32 }
 
-    Vel { x: f32, y: f32, d: f32 = 10.0 },
+/// Rotation and scale around the entity's `Pos`. Entities without one are upright at scale 1.
+#[component]
+pub struct Transform {
+    #[step(1.0)]
+    #[tooltip("Degrees")]
+    pub rotation: f32,
+    #[default([1.0, 1.0])]
+    #[tooltip("Negative values mirror the sprite")]
+    pub scale: [f32; 2],
+}
 
-    Render { 
-        s_id: crate::sprite_manager::SpriteId = crate::sprite_manager::SpriteId(0),
-        w: f32 = PPU, 
-        h: f32 = PPU, 
-        color: [f32; 4] = [1.0, 1.0, 1.0, 1.0],
-        layer: f32 = 0.0,
-        flip_x: bool = false,
-        flip_y: bool = false,
+#[component]
+pub struct Vel { 
+    pub x: f32, 
+    pub y: f32, 
+    #[default(10.0)]
+    #[range(0.0, 60.0)]
+    #[tooltip("Damping: how quickly the velocity decays, per second")]
+    pub d: f32,
+}
 
-        #[serde(skip)]
-        cached_sprite: Option<crate::sprite_manager::SpriteData> = None,
-    },
+#[component]
+pub struct Render { 
+    #[default(crate::sprite_manager::SpriteId(0))]
+    pub s_id: crate::sprite_manager::SpriteId,
+    #[default(PPU)]
+    pub w: f32, 
+    #[default(PPU)]
+    pub h: f32, 
+    #[default([1.0, 1.0, 1.0, 1.0])]
+    pub color: [f32; 4],
+    #[step(1.0)]
+    #[tooltip("Draw order; higher layers are drawn on top")]
+    pub layer: f32,
+    pub flip_x: bool,
+    pub flip_y: bool,
 
-    CameraAnchor { zoom: f32 = 1.0, smoothness: f32 = 1.0 },
+    #[serde(skip)]
+    #[hidden]
+    pub cached_sprite: Option<crate::sprite_manager::SpriteData>,
+}
 
-    Player { speed: f32 = 50.0 },
+#[component]
+pub struct CameraAnchor { 
+    #[default(1.0)]
+    #[range(0.1, 7.0)]
+    pub zoom: f32, 
+    #[default(1.0)]
+    #[range(0.0, 1.0)]
+    #[slider]
+    pub smoothness: f32,
+}
 
-    Collider { 
-        size: [f32; 2] = [PPU, PPU],
-        offset: [f32; 2] = [PPU, PPU],
-        is_static: bool = true 
-    },
-    TileMap {
-        width: usize = 100,
-        height: usize = 100,
-        tile_size: f32 = PPU,
-        brush_sprite: crate::sprite_manager::SpriteId = crate::sprite_manager::SpriteId(0),
-        tiles: Vec<u32> = vec![0; 10000], 
-    },
+#[component]
+pub struct Player { 
+    #[default(50.0)]
+    pub speed: f32,
+}
+
+#[component(version = 1)]
+pub struct Collider { 
+    #[default([PPU, PPU])]
+    pub size: [f32; 2],
+    pub offset: [f32; 2],
+    #[default(true)]
+    pub is_static: bool,
+}
+
+/// Early scenes saved the box as separate `w` and `h`.
+#[migration(Collider, from = 0)]
+fn collider_size(m: &mut Migrate) {
+    if let (Some(w), Some(h)) = (m.take("w"), m.take("h")) {
+        m.set("size", serde_json::json!([w, h]));
+    }
+}
+
+#[component]
+pub struct TileMap {
+    #[default(100)]
+    #[readonly]
+    #[tooltip("Fixed once created; `tiles` holds width * height entries")]
+    pub width: usize,
+    #[default(100)]
+    #[readonly]
+    pub height: usize,
+    #[default(PPU)]
+    #[step(16.0)]
+    #[range(16.0, 1024.0)]
+    pub tile_size: f32,
+    #[default(crate::sprite_manager::SpriteId(0))]
+    pub brush_sprite: crate::sprite_manager::SpriteId,
+    #[default(vec![0; 10000])]
+    #[tooltip("Sprite id of every tile (0 for none), row by row")]
+    pub tiles: Vec<u32>, 
 }
 
 impl Render {
@@ -42,4 +107,95 @@ impl Render {
     pub fn g(&self) -> f32 { self.color[1] }
     pub fn b(&self) -> f32 { self.color[2] }
     pub fn a(&self) -> f32 { self.color[3] }
-}
\ No newline at end of file
+}
+
+impl Transform {
+    pub fn radians(&self) -> f32 {
+        self.rotation.to_radians()
+    }
+
+    /// Scales and rotates an offset from the entity's local space into world space.
+    pub fn apply(&self, local: Vec2) -> Vec2 {
+        Vec2::from_angle(self.radians()).rotate(local * Vec2::from(self.scale))
+    }
+
+    /// Inverse of `apply`; an axis scaled to zero maps back to 0.
+    pub fn unapply(&self, offset: Vec2) -> Vec2 {
+        let unrotated = Vec2::from_angle(-self.radians()).rotate(offset);
+        let inverse = |scale: f32| if scale == 0.0 { 0.0 } else { 1.0 / scale };
+        unrotated * vec2(inverse(self.scale[0]), inverse(self.scale[1]))
+    }
+
+    /// World-space corners of a `size` box centered `offset` away from `pos` in local space.
+    pub fn corners(&self, pos: Vec2, offset: Vec2, size: Vec2) -> [Vec2; 4] {
+        let half = size / 2.0;
+        [vec2(-half.x, -half.y), vec2(half.x, -half.y), vec2(half.x, half.y), vec2(-half.x, half.y)]
+            .map(|corner| pos + self.apply(offset + corner))
+    }
+
+    /// Axis-aligned bounding box of the same box as `corners`.
+    pub fn bounds(&self, pos: Vec2, offset: Vec2, size: Vec2) -> Rect {
+        let corners = self.corners(pos, offset, size);
+        let min = corners.iter().fold(corners[0], |min, c| min.min(*c));
+        let max = corners.iter().fold(corners[0], |max, c| max.max(*c));
+        Rect::new(min.x, min.y, max.x - min.x, max.y - min.y)
+    }
+
+    /// Whether `point` lies inside a `size` box centered on `pos` and turned with this transform.
+    /// A box scaled to zero on either axis has no area and contains nothing.
+    pub fn box_contains(&self, pos: Vec2, size: Vec2, point: Vec2) -> bool {
+        if self.scale[0] == 0.0 || self.scale[1] == 0.0 {
+            return false;
+        }
+        let local = self.unapply(point - pos);
+        local.x.abs() <= size.x / 2.0 && local.y.abs() <= size.y / 2.0
+    }
+}
+
+impl Collider {
+    /// Axis-aligned bounds used by physics; a rotated collider takes the bounding box of its corners.
+    pub fn rect(&self, pos: &Pos, transform: Option<&Transform>) -> Rect {
+        let (w, h) = (self.size[0], self.size[1]);
+        let Some(transform) = transform else {
+            return Rect::new(pos.x + self.offset[0] - w / 2.0, pos.y + self.offset[1] - h / 2.0, w, h);
+        };
+        transform.bounds(vec2(pos.x, pos.y), Vec2::from(self.offset), vec2(w, h))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::{self, SceneError, SceneFormat};
+
+    fn load(text: &str) -> Result<hecs::World, SceneError> {
+        let mut world = hecs::World::new();
+        scene::load_scene(&mut world, text.as_bytes(), SceneFormat::Text)?;
+        Ok(world)
+    }
+
+    #[test]
+    fn version_0_colliders_get_their_size_from_w_and_h() {
+        let world = load(r#"{ "7": { "Collider": { "w": 12.0, "h": 30.0, "offset": [1.0, 2.0], "is_static": false } } }"#).unwrap();
+        let mut colliders = world.query::<&Collider>();
+        let [(_, collider)] = colliders.iter().collect::<Vec<_>>()[..] else {
+            panic!("expected one collider");
+        };
+        assert_eq!(collider.size, [12.0, 30.0]);
+        assert_eq!(collider.offset, [1.0, 2.0]);
+        assert!(!collider.is_static);
+    }
+
+    #[test]
+    fn version_1_colliders_load_unchanged() {
+        let text = r#"{ "version": 1, "versions": { "Collider": 1 }, "entities": { "7": { "Collider": { "size": [12.0, 30.0], "offset": [0.0, 0.0], "is_static": true } } } }"#;
+        let world = load(text).unwrap();
+        assert_eq!(world.query::<&Collider>().iter().map(|(_, c)| c.size).collect::<Vec<_>>(), [[12.0, 30.0]]);
+    }
+
+    #[test]
+    fn colliders_from_a_newer_build_are_refused() {
+        let text = r#"{ "version": 1, "versions": { "Collider": 2 }, "entities": { "7": { "Collider": {} } } }"#;
+        assert!(matches!(load(text), Err(SceneError::Newer { saved: 2, supported: 1, .. })));
+    }
+}
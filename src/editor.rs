@@ -2,6 +2,27 @@ use hecs::{World, CommandBuffer, Entity};
 use macroquad::prelude::*;
 use egui_macroquad::egui;
 use crate::components::*;
+#[cfg(debug_assertions)] use crate::scene_manager::{self, SceneManager};
+use crate::events::Event;
+#[cfg(debug_assertions)] use crate::events::Events;
+#[cfg(debug_assertions)] use crate::systems::{Schedule, Stage};
+#[cfg(debug_assertions)] use crate::profiler::{Profiler, TimingStats, PROFILER_WINDOW};
+#[cfg(debug_assertions)] use crate::inspector::{draw_component_menu, draw_entity_inspector, finish_pick, is_picking};
+#[cfg(debug_assertions)] use crate::hierarchy;
+#[cfg(debug_assertions)] use crate::prefab;
+
+/// Sent by the editor UI so gameplay and tooling systems can react to scene edits.
+#[allow(dead_code)]
+pub enum EditorAction {
+    Spawned(Entity),
+    Despawned(Entity),
+    Duplicated { source: Entity, copy: Entity },
+    SceneSaved,
+    /// Sent by `scene_manager::apply_requests` once a load finishes, whoever asked for it.
+    SceneLoaded,
+}
+
+impl Event for EditorAction {}
 
 #[cfg(debug_assertions)]
 pub fn handle_editor_input(
@@ -37,10 +58,10 @@ pub fn handle_editor_input(
 
                         if grid_x >= 0 && grid_x < tm.width as i32 && grid_y >= 0 && grid_y < tm.height as i32 {
                             let idx = (grid_y as usize) * tm.width + (grid_x as usize);
-                            if is_mouse_button_down(MouseButton::Left) {
-                                tm.tiles[idx] = tm.brush_sprite.0;
-                            } else {
-                                tm.tiles[idx] = 0; 
+                            let brush = if is_mouse_button_down(MouseButton::Left) { tm.brush_sprite.0 } else { 0 };
+                            // `tiles` can be resized in the inspector, so it may be shorter than the grid
+                            if let Some(tile) = tm.tiles.get_mut(idx) {
+                                *tile = brush;
                             }
                         }
                     }
@@ -58,13 +79,23 @@ pub fn handle_editor_input(
         *ctx_screen = None;
 
         let mut clicked = None;
-        for (id, (pos, ren)) in world.query_mut::<(&Pos, &Render)>() {
-            let rect = Rect::new(pos.x - ren.w / 2.0, pos.y - ren.h / 2.0, ren.w, ren.h);
-            if rect.contains(mouse_world) {
+        for (id, (pos, ren, transform)) in world.query_mut::<(&Pos, &Render, Option<&Transform>)>() {
+            let transform = transform.cloned().unwrap_or_default();
+            if transform.box_contains(vec2(pos.x, pos.y), vec2(ren.w, ren.h), mouse_world) {
                 clicked = Some(id);
                 *offset = vec2(pos.x, pos.y) - mouse_world;
             }
         }
+
+        // An `EntityRef` field waiting for a target takes the click instead of the selection
+        let mut picking = false;
+        egui_macroquad::cfg(|ctx| {
+            picking = is_picking(ctx);
+            finish_pick(ctx, clicked);
+        });
+        if picking {
+            return;
+        }
         *selected = clicked; 
         *dragging = clicked; 
     }
@@ -83,6 +114,7 @@ pub fn handle_editor_input(
                     pos.y = target_y;
                 }
             }
+            hierarchy::update_offset(world, *entity);
         }
     }
 
@@ -94,6 +126,7 @@ pub fn handle_editor_input(
 }
 
 #[cfg(debug_assertions)]
+#[allow(clippy::too_many_arguments)]
 pub fn draw_editor(
     world: &mut World, 
     selected_entity: &mut Option<Entity>, 
@@ -101,7 +134,11 @@ pub fn draw_editor(
     block_input: &mut bool,
     ctx_menu_world: &mut Option<macroquad::math::Vec2>,
     ctx_menu_screen: &mut Option<macroquad::math::Vec2>,
-    sprite_manager: &crate::sprite_manager::SpriteManager 
+    sprite_manager: &crate::sprite_manager::SpriteManager,
+    events: &mut Events,
+    scenes: &mut SceneManager,
+    schedule: &mut Schedule,
+    profiler: &Profiler
 ) {
     let mut cmd = CommandBuffer::new();
     egui_macroquad::ui(|egui_ctx| {
@@ -116,11 +153,13 @@ pub fn draw_editor(
 
                     if let Some(entity) = *selected_entity {
                         if ui.button("🗑 Delete entity").clicked() {
-                            cmd.despawn(entity);
+                            hierarchy::despawn_recursive(&mut cmd, world, entity);
+                            events.send(EditorAction::Despawned(entity));
                             *selected_entity = None;
                         }
                         if ui.button("🗐 Copy entity").clicked() {
-                            let new_entity = duplicate_entity(world, entity);
+                            let new_entity = hierarchy::duplicate_recursive(world, entity);
+                            events.send(EditorAction::Duplicated { source: entity, copy: new_entity });
                             *selected_entity = Some(new_entity);
                         }
                         ui.separator();
@@ -128,6 +167,7 @@ pub fn draw_editor(
 
                     if ui.button("Spawn Empty").clicked() {
                         let new_ent = world.spawn((Pos { x: world_pos.x, y: world_pos.y },)); 
+                        events.send(EditorAction::Spawned(new_ent));
                         *selected_entity = Some(new_ent);
                         *ctx_menu_world = None;
                         *ctx_menu_screen = None;
@@ -137,6 +177,7 @@ pub fn draw_editor(
                             Pos { x: world_pos.x, y: world_pos.y },
                             Render::default(), 
                         ));
+                        events.send(EditorAction::Spawned(new_ent));
                         *selected_entity = Some(new_ent);
                         *ctx_menu_world = None;
                         *ctx_menu_screen = None;
@@ -146,10 +187,31 @@ pub fn draw_editor(
                             Pos { x: world_pos.x, y: world_pos.y },
                             TileMap::default(), 
                         ));
+                        events.send(EditorAction::Spawned(new_ent));
                         *selected_entity = Some(new_ent);
                         *ctx_menu_world = None;
                         *ctx_menu_screen = None;
                     }
+                    ui.menu_button("📦 Spawn Prefab", |ui| {
+                        let prefabs = prefab::list();
+                        if prefabs.is_empty() {
+                            ui.label("No prefabs in assets/prefabs");
+                        }
+                        for name in prefabs {
+                            if ui.button(&name).clicked()
+                                && let Some(root) = spawn_prefab(world, &name, events)
+                            {
+                                if let Ok(mut pos) = world.get::<&mut Pos>(root) {
+                                    pos.x = world_pos.x;
+                                    pos.y = world_pos.y;
+                                }
+                                hierarchy::update(world);
+                                *selected_entity = Some(root);
+                                *ctx_menu_world = None;
+                                *ctx_menu_screen = None;
+                            }
+                        }
+                    });
                 });
             });
         }
@@ -169,63 +231,86 @@ pub fn draw_editor(
                 ui.separator();
 
                 if ui.button("💾 Save").clicked() {
-                    let bytes = save_scene(world); 
-                    if let Err(e) = std::fs::write("Scene.bin", bytes) {
-                        println!("cargo:warning=Failed to write file: {}", e);
+                    match scene_manager::save_loaded(world, scenes) {
+                        Ok(()) => events.send(EditorAction::SceneSaved),
+                        Err(e) => println!("cargo:warning=Failed to write file: {}", e),
                     }
                 }
 
                 ui.add_space(4.0);
 
                 if ui.button("📂 Load").clicked() {
-                    if let Ok(bytes) = std::fs::read("Scene.bin") {
-                        load_scene(world, &bytes); 
-                        *selected_entity = None;
-                        
-                        for (_id, ren) in world.query_mut::<&mut Render>() {
-                            ren.cached_sprite = None;
-                        }
-                    }
+                    scenes.reload();
                 }
 
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                     ui.label(format!("FPS: {:.0}", macroquad::time::get_fps()));
+                    draw_frame_graph(ui, profiler);
                 });
             });
         });
 
+        if let Some(e) = scenes.take_error() {
+            egui_ctx.data_mut(|data| data.insert_temp(scene_error_id(), e));
+        }
+        draw_scene_error(egui_ctx);
+
         egui::Window::new("🌍 Hierarchy")
             .default_size([200.0, 400.0]) 
             .vscroll(true) 
             .show(egui_ctx, |ui| {
                 if ui.button("✨ Spawn Entity").clicked() {
                     let new_ent = world.spawn(());
+                    events.send(EditorAction::Spawned(new_ent));
                     *selected_entity = Some(new_ent);
                 }
                 
                 ui.separator();
 
-                let entities: Vec<Entity> = world.iter().map(|entity_ref| entity_ref.entity()).collect();
-                for entity in entities {
-                    ui.horizontal(|ui| {
-                        let is_selected = *selected_entity == Some(entity);
-                        if ui.selectable_label(is_selected, format!("ID: {:?}", entity.id())).clicked() {
-                            *selected_entity = Some(entity);
-                        }   
-
-                        if ui.button("🗐").on_hover_text("Duplicate").clicked() {
-                            let new_entity = duplicate_entity(world, entity);
-                            *selected_entity = Some(new_entity);
-                        }
+                let roots: Vec<Entity> = world.iter()
+                    .map(|entity_ref| entity_ref.entity())
+                    .filter(|entity| hierarchy::parent(world, *entity).is_none())
+                    .collect();
+                let mut tree = HierarchyUi { selected: selected_entity, cmd: &mut cmd, events, reparent: None };
+                for entity in roots {
+                    draw_hierarchy_node(ui, world, entity, &mut tree);
+                }
 
-                        if ui.button("🗑").clicked() {
-                            cmd.despawn(entity);
-                            if is_selected { *selected_entity = None; }
-                        }
-                    });
+                if egui::DragAndDrop::has_payload_of_type::<Entity>(ui.ctx()) {
+                    let zone = ui.add(egui::Label::new(egui::RichText::new("⤒ Drop here to detach").weak()).sense(egui::Sense::hover()));
+                    if let Some(dragged) = zone.dnd_release_payload::<Entity>() {
+                        tree.reparent = Some((*dragged, None));
+                    }
+                }
+                if let Some((child, parent)) = tree.reparent {
+                    hierarchy::set_parent(world, child, parent);
                 }
             });
 
+        egui::Window::new("🗺 Scenes")
+            .default_open(false)
+            .default_size([220.0, 250.0])
+            .vscroll(true)
+            .show(egui_ctx, |ui| {
+                draw_scenes(ui, scenes);
+            });
+
+        egui::Window::new("📦 Prefabs")
+            .default_open(false)
+            .default_size([200.0, 250.0])
+            .vscroll(true)
+            .show(egui_ctx, |ui| {
+                draw_prefabs(ui, world, selected_entity, events);
+            });
+
+        egui::Window::new("⚙ Systems")
+            .default_open(false)
+            .default_size([420.0, 300.0])
+            .vscroll(true)
+            .show(egui_ctx, |ui| {
+                draw_systems_table(ui, schedule, profiler);
+            });
+
         egui::Window::new("🛠 Inspector")
             .default_size([250.0, 400.0])
             .vscroll(true)
@@ -247,4 +332,268 @@ pub fn draw_editor(
     });
 
     cmd.run_on(world);
-}
\ No newline at end of file
+}
+
+#[cfg(debug_assertions)]
+fn spawn_prefab(world: &mut World, name: &str, events: &mut Events) -> Option<Entity> {
+    match prefab::instantiate(world, name) {
+        Ok(root) => {
+            events.send(EditorAction::Spawned(root));
+            Some(root)
+        }
+        Err(e) => {
+            println!("cargo:warning=Failed to spawn prefab: {}", e);
+            None
+        }
+    }
+}
+
+/// Saves the selection as a prefab and lists the prefabs in `assets/prefabs` to spawn.
+#[cfg(debug_assertions)]
+fn draw_prefabs(ui: &mut egui::Ui, world: &mut World, selected: &mut Option<Entity>, events: &mut Events) {
+    let name_id = egui::Id::new("prefab_name");
+    let mut name = ui.data(|data| data.get_temp::<String>(name_id)).unwrap_or_default();
+    ui.horizontal(|ui| {
+        ui.add(egui::TextEdit::singleline(&mut name).hint_text("Name").desired_width(110.0));
+        let can_save = selected.is_some_and(|entity| world.contains(entity)) && !name.trim().is_empty();
+        if ui.add_enabled(can_save, egui::Button::new("💾 Save selected")).clicked() {
+            match prefab::save_prefab(world, selected.unwrap(), name.trim()) {
+                Ok(file) => println!("Saved prefab {}", file),
+                Err(e) => println!("cargo:warning=Failed to save prefab: {}", e),
+            }
+        }
+    });
+    ui.data_mut(|data| data.insert_temp(name_id, name));
+
+    ui.separator();
+
+    for name in prefab::list() {
+        ui.horizontal(|ui| {
+            ui.label(&name);
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.small_button("➕ Spawn").clicked()
+                    && let Some(root) = spawn_prefab(world, &name, events)
+                {
+                    *selected = Some(root);
+                }
+            });
+        });
+    }
+}
+
+/// Lists the scenes in `assets/scenes` to switch to, or to load on top of the active one.
+#[cfg(debug_assertions)]
+fn draw_scenes(ui: &mut egui::Ui, scenes: &mut SceneManager) {
+    let names = scene_manager::list();
+    if names.is_empty() {
+        ui.label("No scenes in assets/scenes");
+    }
+    for name in names {
+        let active = scenes.active().is_some_and(|scene| scene.name == name);
+        let additive = scenes.additive().iter().any(|scene| scene.name == name);
+        ui.horizontal(|ui| {
+            if active {
+                ui.strong(&name);
+            } else {
+                ui.label(&name);
+            }
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if additive {
+                    if ui.small_button("✖ Unload").clicked() {
+                        scenes.unload(&name);
+                    }
+                } else if !active {
+                    if ui.small_button("➕ Add").clicked() {
+                        scenes.load_additive(&name);
+                    }
+                    if ui.small_button("▶ Switch").clicked() {
+                        scenes.switch(&name);
+                    }
+                }
+            });
+        });
+    }
+}
+
+#[cfg(debug_assertions)]
+fn scene_error_id() -> egui::Id {
+    egui::Id::new("scene_load_error")
+}
+
+/// Shows why the last scene load failed until it is dismissed. The world is unchanged.
+#[cfg(debug_assertions)]
+fn draw_scene_error(ctx: &egui::Context) {
+    let Some(message) = ctx.data(|data| data.get_temp::<String>(scene_error_id())) else {
+        return;
+    };
+    let mut open = true;
+    egui::Window::new("⚠ Scene not loaded")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .show(ctx, |ui| {
+            ui.label(&message);
+            ui.label("The world was left as it was.");
+            ui.add_space(4.0);
+            if ui.button("OK").clicked() {
+                open = false;
+            }
+        });
+    if !open {
+        ctx.data_mut(|data| data.remove::<String>(scene_error_id()));
+    }
+}
+
+#[cfg(debug_assertions)]
+struct HierarchyUi<'a> {
+    selected: &'a mut Option<Entity>,
+    cmd: &'a mut CommandBuffer,
+    events: &'a mut Events,
+    /// Child and new parent of a drag-and-drop, applied once the tree is drawn.
+    reparent: Option<(Entity, Option<Entity>)>,
+}
+
+/// One row of the Hierarchy window and, below it, its children.
+#[cfg(debug_assertions)]
+fn draw_hierarchy_node(ui: &mut egui::Ui, world: &mut World, entity: Entity, tree: &mut HierarchyUi) {
+    let children = hierarchy::children(world, entity);
+    if children.is_empty() {
+        ui.horizontal(|ui| draw_hierarchy_row(ui, world, entity, tree));
+        return;
+    }
+
+    egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), ui.make_persistent_id(("hierarchy", entity)), true)
+    .show_header(ui, |ui| draw_hierarchy_row(ui, world, entity, tree))
+    .body(|ui| {
+        for child in children {
+            draw_hierarchy_node(ui, world, child, tree);
+        }
+    });
+}
+
+#[cfg(debug_assertions)]
+fn draw_hierarchy_row(ui: &mut egui::Ui, world: &mut World, entity: Entity, tree: &mut HierarchyUi) {
+    let is_selected = *tree.selected == Some(entity);
+    let label = ui.dnd_drag_source(egui::Id::new(("hierarchy_drag", entity)), entity, |ui| {
+        ui.selectable_label(is_selected, format!("ID: {:?}", entity.id()))
+    }).inner;
+
+    if label.clicked() {
+        *tree.selected = Some(entity);
+    }
+    if label.dnd_hover_payload::<Entity>().is_some_and(|dragged| *dragged != entity) {
+        ui.painter().rect_stroke(label.rect, 2.0, (1.0, egui::Color32::LIGHT_BLUE), egui::StrokeKind::Outside);
+    }
+    if let Some(dragged) = label.dnd_release_payload::<Entity>() {
+        tree.reparent = Some((*dragged, Some(entity)));
+    }
+
+    if ui.button("🗐").on_hover_text("Duplicate").clicked() {
+        let new_entity = hierarchy::duplicate_recursive(world, entity);
+        tree.events.send(EditorAction::Duplicated { source: entity, copy: new_entity });
+        *tree.selected = Some(new_entity);
+    }
+
+    if ui.button("🗑").clicked() {
+        hierarchy::despawn_recursive(tree.cmd, world, entity);
+        tree.events.send(EditorAction::Despawned(entity));
+        if is_selected { *tree.selected = None; }
+    }
+}
+
+#[cfg(debug_assertions)]
+#[derive(Clone, Copy, PartialEq)]
+enum SystemSort { Order, Name, Avg, Max }
+
+#[cfg(debug_assertions)]
+fn draw_systems_table(ui: &mut egui::Ui, schedule: &mut Schedule, profiler: &Profiler) {
+    struct Row { name: &'static str, stage: Option<Stage>, stats: Option<TimingStats> }
+
+    let mut rows: Vec<Row> = Stage::ALL.iter()
+        .flat_map(|&stage| schedule.systems(stage).iter().map(move |s| (stage, s.name)))
+        .map(|(stage, name)| Row { name, stage: Some(stage), stats: profiler.stats(name) })
+        .collect();
+    // Pseudo-systems like physics and rendering are timed by `main` but aren't in the schedule
+    let mut extra: Vec<&'static str> = profiler.names().filter(|n| !rows.iter().any(|r| r.name == *n)).collect();
+    extra.sort();
+    rows.extend(extra.into_iter().map(|name| Row { name, stage: None, stats: profiler.stats(name) }));
+
+    let mut parallel = schedule.is_parallel();
+    ui.add_enabled_ui(cfg!(not(target_arch = "wasm32")), |ui| {
+        if ui.checkbox(&mut parallel, "Run in parallel")
+            .on_hover_text("Runs `#[system(parallel)]` systems whose declared data access doesn't overlap on worker threads")
+            .changed()
+        {
+            schedule.set_parallel(parallel);
+        }
+    });
+    ui.separator();
+
+    let sort_id = ui.make_persistent_id("systems_sort");
+    let mut sort = ui.data_mut(|d| d.get_temp::<SystemSort>(sort_id).unwrap_or(SystemSort::Order));
+    let by_time = |stats: &Option<TimingStats>, pick: fn(&TimingStats) -> f32| stats.as_ref().map(pick).unwrap_or(0.0);
+    match sort {
+        SystemSort::Order => {}
+        SystemSort::Name => rows.sort_by(|a, b| a.name.cmp(b.name)),
+        SystemSort::Avg => rows.sort_by(|a, b| by_time(&b.stats, |s| s.avg).total_cmp(&by_time(&a.stats, |s| s.avg))),
+        SystemSort::Max => rows.sort_by(|a, b| by_time(&b.stats, |s| s.max).total_cmp(&by_time(&a.stats, |s| s.max))),
+    }
+
+    egui::Grid::new("systems_table")
+        .num_columns(6)
+        .spacing([12.0, 4.0])
+        .striped(true)
+        .show(ui, |ui| {
+            ui.label("");
+            for (label, key) in [("System", SystemSort::Name), ("Stage", SystemSort::Order), ("Avg ms", SystemSort::Avg)] {
+                if ui.selectable_label(sort == key, label).clicked() { sort = key; }
+            }
+            ui.label("Min ms");
+            if ui.selectable_label(sort == SystemSort::Max, "Max ms").clicked() { sort = SystemSort::Max; }
+            ui.end_row();
+
+            for row in rows {
+                if row.stage.is_some() {
+                    let mut enabled = schedule.is_enabled(row.name);
+                    if ui.checkbox(&mut enabled, "").changed() {
+                        schedule.set_enabled(row.name, enabled);
+                    }
+                } else {
+                    ui.label("");
+                }
+                ui.label(row.name);
+                ui.label(row.stage.map(|s| format!("{:?}", s)).unwrap_or_else(|| "-".to_string()));
+                match row.stats {
+                    Some(stats) => {
+                        ui.label(format!("{:.3}", stats.avg));
+                        ui.label(format!("{:.3}", stats.min));
+                        ui.label(format!("{:.3}", stats.max));
+                    }
+                    None => { ui.label("-"); ui.label("-"); ui.label("-"); }
+                }
+                ui.end_row();
+            }
+        });
+
+    ui.data_mut(|d| d.insert_temp(sort_id, sort));
+}
+
+#[cfg(debug_assertions)]
+fn draw_frame_graph(ui: &mut egui::Ui, profiler: &Profiler) {
+    let (rect, response) = ui.allocate_exact_size(egui::vec2(120.0, 18.0), egui::Sense::hover());
+    let times = profiler.frame_times();
+    // Never zoom in past 30 FPS so a smooth 60 FPS run reads as a flat line near the bottom
+    let peak = times.iter().copied().fold(1000.0 / 30.0, f32::max);
+
+    let step = rect.width() / (PROFILER_WINDOW - 1) as f32;
+    let points: Vec<egui::Pos2> = times.iter().enumerate()
+        .map(|(i, t)| egui::pos2(rect.left() + i as f32 * step, rect.bottom() - t / peak * rect.height()))
+        .collect();
+
+    ui.painter().rect_filled(rect, 2.0, egui::Color32::from_black_alpha(80));
+    ui.painter().add(egui::Shape::line(points, egui::Stroke::new(1.0, egui::Color32::LIGHT_GREEN)));
+
+    if let Some(last) = times.back() {
+        response.on_hover_text(format!("Frame: {:.2} ms (graph top: {:.1} ms)", last, peak));
+    }
+}
@@ -1,6 +1,17 @@
-pub use engine_macros::system;
+pub use engine_macros::{component, migration, system, Inspect};
 pub use hecs::{World, Entity};
 pub use crate::components::*;
-pub use crate::systems::SysCtx;
+pub use crate::scene::{save_scene, load_scene, scene_path, SceneFormat};
+pub use crate::migrate::Migrate;
+pub use crate::scene_manager::{SceneManager, SceneMember};
+pub use crate::entity_ref::EntityRef;
+pub use crate::hierarchy::{Parent, Children};
+pub use crate::prefab::PrefabInstance;
+pub use crate::systems::{SysCtx, Query, Commands, RunState, conditions};
+pub use crate::resources::{Resource, Resources, Res, ResMut};
+pub use crate::events::{Event, Events, EventWriter, EventReader};
+pub use crate::state::GameState;
+pub use crate::profiler::Profiler;
+pub use crate::scripting::{Script, ScriptHost};
 pub use crate::sprite_manager::*;
 pub use macroquad::{prelude::*};
\ No newline at end of file
@@ -0,0 +1,65 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use hecs::{Entity, World};
+
+/// A reference from one entity to another that can be stored in a component.
+/// Saved as the target's scene-local id and remapped to the new entity when the scene
+/// is loaded or the entities are duplicated together.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Hash)]
+pub struct EntityRef(pub Option<Entity>);
+
+impl EntityRef {
+    pub const NONE: Self = Self(None);
+
+    pub fn new(entity: Entity) -> Self {
+        Self(Some(entity))
+    }
+
+    /// The target, if it is still alive.
+    pub fn get(&self, world: &World) -> Option<Entity> {
+        self.0.filter(|entity| world.contains(*entity))
+    }
+}
+
+struct Remap {
+    ids: HashMap<u64, Entity>,
+    /// Targets missing from `ids` stay as they are instead of becoming `None`.
+    keep_unmapped: bool,
+}
+
+thread_local! {
+    static REMAP: RefCell<Option<Remap>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` with every `EntityRef` deserialized inside it remapped through `ids` (old entity
+/// bits to new entity). Outside of it ids are taken as they are, which is what scripts need.
+pub(crate) fn with_remap<R>(ids: &HashMap<u64, Entity>, keep_unmapped: bool, f: impl FnOnce() -> R) -> R {
+    let previous = REMAP.replace(Some(Remap { ids: ids.clone(), keep_unmapped }));
+    let result = f();
+    REMAP.set(previous);
+    result
+}
+
+fn remap(bits: u64) -> Option<Entity> {
+    REMAP.with_borrow(|remap| match remap {
+        Some(remap) => match remap.ids.get(&bits) {
+            Some(entity) => Some(*entity),
+            None if remap.keep_unmapped => Entity::from_bits(bits),
+            None => None,
+        },
+        None => Entity::from_bits(bits),
+    })
+}
+
+impl serde::Serialize for EntityRef {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.map(|entity| entity.to_bits().get()).serialize(serializer)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for EntityRef {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bits = Option::<u64>::deserialize(deserializer)?;
+        Ok(Self(bits.and_then(remap)))
+    }
+}
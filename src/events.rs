@@ -0,0 +1,218 @@
+use std::any::{Any, TypeId, type_name};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Mutex;
+
+/// Marker for types that can be sent between systems through `Events`.
+pub trait Event: Any + Send + Sync {}
+
+/// Queue for one event type. Frame-rate and fixed-step systems read through separate windows:
+/// events sent so far become readable to frame-rate systems after `Events::update` at the end
+/// of the frame, and to fixed-step systems after `Events::update_fixed` at the start of the next
+/// fixed step. Each window moves past what it showed, so every reader sees each event exactly once
+/// regardless of system order or how many fixed steps a frame runs.
+pub struct Channel<T> {
+    /// Sent events no window has moved past yet; `log[0]` has sequence number `start`.
+    log: Vec<T>,
+    start: usize,
+    frame: Range<usize>,
+    fixed: Range<usize>,
+    current: Mutex<Vec<T>>,
+}
+
+impl<T> Channel<T> {
+    fn new() -> Self {
+        Self { log: Vec::new(), start: 0, frame: 0..0, fixed: 0..0, current: Mutex::new(Vec::new()) }
+    }
+
+    /// Moves everything sent since the last swap into the log.
+    fn flush(&mut self) {
+        let current = self.current.get_mut().unwrap_or_else(|e| e.into_inner());
+        self.log.append(current);
+    }
+
+    fn advance(&mut self, fixed: bool) {
+        self.flush();
+        let end = self.start + self.log.len();
+        let window = if fixed { &mut self.fixed } else { &mut self.frame };
+        *window = window.end..end;
+
+        let keep = self.frame.start.min(self.fixed.start);
+        self.log.drain(..keep - self.start);
+        self.start = keep;
+    }
+
+    fn window(&self, fixed: bool) -> &[T] {
+        let window = if fixed { &self.fixed } else { &self.frame };
+        &self.log[window.start - self.start..window.end - self.start]
+    }
+}
+
+trait AnyChannel: Any + Send + Sync {
+    fn advance(&mut self, fixed: bool);
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: Event> AnyChannel for Channel<T> {
+    fn advance(&mut self, fixed: bool) {
+        Channel::advance(self, fixed);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[derive(Default)]
+pub struct Events {
+    channels: HashMap<TypeId, Box<dyn AnyChannel>>,
+    /// Set while fixed-step systems run, so their readers use the fixed window.
+    fixed_step: bool,
+}
+
+pub struct EventWriter<'a, T: Event> {
+    channel: &'a Channel<T>,
+}
+
+pub struct EventReader<'a, T: Event> {
+    events: &'a [T],
+}
+
+impl Events {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<T: Event>(&mut self) {
+        self.channels.entry(TypeId::of::<T>()).or_insert_with(|| {
+            Box::new(Channel::<T>::new())
+        });
+    }
+
+    /// Sends from engine code that owns the bus, registering the channel on first use.
+    pub fn send<T: Event>(&mut self, event: T) {
+        self.register::<T>();
+        self.writer::<T>().send(event);
+    }
+
+    fn channel<T: Event>(&self) -> &Channel<T> {
+        self.channels.get(&TypeId::of::<T>())
+            .and_then(|c| c.as_any().downcast_ref())
+            .unwrap_or_else(|| panic!("event `{}` was used before being registered", type_name::<T>()))
+    }
+
+    pub fn writer<T: Event>(&self) -> EventWriter<'_, T> {
+        EventWriter { channel: self.channel() }
+    }
+
+    pub fn reader<T: Event>(&self) -> EventReader<'_, T> {
+        EventReader { events: self.channel().window(self.fixed_step) }
+    }
+
+    /// Makes everything sent so far readable to frame-rate systems. Called once at the end of each frame.
+    pub fn update(&mut self) {
+        for channel in self.channels.values_mut() {
+            channel.advance(false);
+        }
+    }
+
+    /// Makes everything sent so far readable to fixed-step systems. Called at the start of each fixed step.
+    pub fn update_fixed(&mut self) {
+        for channel in self.channels.values_mut() {
+            channel.advance(true);
+        }
+    }
+
+    pub(crate) fn set_fixed_step(&mut self, fixed_step: bool) {
+        self.fixed_step = fixed_step;
+    }
+}
+
+impl<T: Event> EventWriter<'_, T> {
+    pub fn send(&self, event: T) {
+        self.channel.current.lock().unwrap_or_else(|e| e.into_inner()).push(event);
+    }
+}
+
+impl<'a, T: Event> EventReader<'a, T> {
+    /// Events sent before the last frame or fixed step began, not yet seen by this kind of system.
+    pub fn iter(&self) -> std::slice::Iter<'a, T> {
+        self.events.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+impl<'a, T: Event> IntoIterator for &EventReader<'a, T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Hit(u32);
+
+    impl Event for Hit {}
+
+    fn read(events: &mut Events, fixed_step: bool) -> Vec<u32> {
+        events.set_fixed_step(fixed_step);
+        let seen = events.reader::<Hit>().iter().map(|hit| hit.0).collect();
+        events.set_fixed_step(false);
+        seen
+    }
+
+    #[test]
+    fn frame_readers_see_each_event_once() {
+        let mut events = Events::new();
+        events.send(Hit(1));
+        assert_eq!(read(&mut events, false), [] as [u32; 0]);
+        events.update();
+        assert_eq!(read(&mut events, false), [1]);
+        events.update();
+        assert_eq!(read(&mut events, false), [] as [u32; 0]);
+    }
+
+    #[test]
+    fn fixed_readers_see_each_event_once_over_several_steps() {
+        let mut events = Events::new();
+        events.send(Hit(1));
+        events.update_fixed();
+        assert_eq!(read(&mut events, true), [1]);
+        events.send(Hit(2));
+        events.update_fixed();
+        assert_eq!(read(&mut events, true), [2]);
+        events.update_fixed();
+        assert_eq!(read(&mut events, true), [] as [u32; 0]);
+        events.update();
+        assert_eq!(read(&mut events, false), [1, 2]);
+    }
+
+    #[test]
+    fn fixed_readers_catch_up_after_frames_without_steps() {
+        let mut events = Events::new();
+        // Sent from a fixed step, e.g. a collision
+        events.send(Hit(1));
+        events.update();
+        events.send(Hit(2));
+        events.update();
+        assert_eq!(read(&mut events, false), [2]);
+
+        events.update_fixed();
+        assert_eq!(read(&mut events, true), [1, 2]);
+        events.update_fixed();
+        assert_eq!(read(&mut events, true), [] as [u32; 0]);
+    }
+}
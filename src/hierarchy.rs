@@ -0,0 +1,206 @@
+use std::collections::HashSet;
+use hecs::{CommandBuffer, Entity, World};
+use engine_macros::component;
+use macroquad::math::{vec2, Vec2};
+use crate::components::{Pos, Transform};
+use crate::entity_ref::EntityRef;
+use crate::reflect::duplicate_entities;
+
+/// Attaches the entity to another one. Its `Pos` (and `Transform`, if it has one) is derived
+/// from the parent's every frame, so children are moved through the local values here.
+#[component]
+pub struct Parent {
+    pub entity: EntityRef,
+    #[tooltip("Position relative to the parent, in its rotated and scaled space")]
+    pub x: f32,
+    #[tooltip("Position relative to the parent, in its rotated and scaled space")]
+    pub y: f32,
+    #[step(1.0)]
+    #[tooltip("Degrees, added to the parent's rotation")]
+    pub rotation: f32,
+    #[default([1.0, 1.0])]
+    #[tooltip("Multiplied with the parent's scale")]
+    pub scale: [f32; 2],
+}
+
+/// The entities whose `Parent` is this one, in hierarchy order. Kept in sync by `update`.
+#[component]
+pub struct Children {
+    #[readonly]
+    pub entities: Vec<EntityRef>,
+}
+
+/// The entity's parent, if it has one that is still alive.
+pub fn parent(world: &World, entity: Entity) -> Option<Entity> {
+    world.get::<&Parent>(entity).ok()?.entity.get(world)
+}
+
+pub fn children(world: &World, entity: Entity) -> Vec<Entity> {
+    world.get::<&Children>(entity)
+        .map(|children| children.entities.iter().filter_map(|child| child.get(world)).collect())
+        .unwrap_or_default()
+}
+
+/// `entity` followed by all of its descendants, depth first.
+pub fn descendants(world: &World, entity: Entity) -> Vec<Entity> {
+    let mut visited = HashSet::new();
+    let mut order = Vec::new();
+    let mut stack = vec![entity];
+    while let Some(node) = stack.pop() {
+        // A `Parent` edited by hand can close a loop; stop instead of spinning
+        if !visited.insert(node) {
+            continue;
+        }
+        order.push(node);
+        stack.extend(children(world, node).into_iter().rev());
+    }
+    order
+}
+
+fn world_pos(world: &World, entity: Entity) -> Vec2 {
+    world.get::<&Pos>(entity).map(|pos| vec2(pos.x, pos.y)).unwrap_or(Vec2::ZERO)
+}
+
+fn world_transform(world: &World, entity: Entity) -> Transform {
+    world.get::<&Transform>(entity).map(|transform| (*transform).clone()).unwrap_or_default()
+}
+
+/// The local values that keep `child` where it is now under `parent`.
+fn relative_to(world: &World, child: Entity, parent: Entity) -> (Vec2, f32, [f32; 2]) {
+    let parent_transform = world_transform(world, parent);
+    let child_transform = world_transform(world, child);
+    let offset = parent_transform.unapply(world_pos(world, child) - world_pos(world, parent));
+    let ratio = |child: f32, parent: f32| if parent == 0.0 { 1.0 } else { child / parent };
+    let scale = [
+        ratio(child_transform.scale[0], parent_transform.scale[0]),
+        ratio(child_transform.scale[1], parent_transform.scale[1]),
+    ];
+    (offset, child_transform.rotation - parent_transform.rotation, scale)
+}
+
+/// Moves `child` under `parent` (or to the top level with `None`) without moving it in the world.
+/// Returns `false` and changes nothing if `parent` is the child itself or one of its descendants.
+pub fn set_parent(world: &mut World, child: Entity, parent: Option<Entity>) -> bool {
+    if parent.is_some_and(|parent| descendants(world, child).contains(&parent)) {
+        return false;
+    }
+
+    if let Some(old) = self::parent(world, child)
+        && let Ok(mut children) = world.get::<&mut Children>(old)
+    {
+        children.entities.retain(|entity| entity.0 != Some(child));
+    }
+
+    match parent {
+        Some(parent) => {
+            let (offset, rotation, scale) = relative_to(world, child, parent);
+            world.insert_one(child, Parent { entity: EntityRef::new(parent), x: offset.x, y: offset.y, rotation, scale }).unwrap();
+            add_child(world, parent, child);
+        }
+        None => {
+            let _ = world.remove_one::<Parent>(child);
+        }
+    }
+    true
+}
+
+fn add_child(world: &mut World, parent: Entity, child: Entity) {
+    let link = EntityRef::new(child);
+    if let Ok(mut children) = world.get::<&mut Children>(parent) {
+        if !children.entities.contains(&link) {
+            children.entities.push(link);
+        }
+        return;
+    }
+    world.insert_one(parent, Children { entities: vec![link] }).unwrap();
+}
+
+/// Recomputes the local values of a child from where it is now, e.g. after the editor dragged it.
+pub fn update_offset(world: &World, entity: Entity) {
+    let Some(parent) = parent(world, entity) else {
+        return;
+    };
+    let (offset, rotation, scale) = relative_to(world, entity, parent);
+    if let Ok(mut link) = world.get::<&mut Parent>(entity) {
+        link.x = offset.x;
+        link.y = offset.y;
+        link.rotation = rotation;
+        link.scale = scale;
+    }
+}
+
+/// Despawns `entity` together with all of its descendants.
+pub fn despawn_recursive(cmd: &mut CommandBuffer, world: &World, entity: Entity) {
+    for entity in descendants(world, entity) {
+        cmd.despawn(entity);
+    }
+}
+
+/// Copies `entity` and all of its descendants. The copy gets the same parent as the original,
+/// and references inside the copied subtree point to the new entities.
+pub fn duplicate_recursive(world: &mut World, entity: Entity) -> Entity {
+    let subtree = descendants(world, entity);
+    let copy = duplicate_entities(world, &subtree)[0];
+    sync_children(world);
+    copy
+}
+
+/// Brings `Children` in line with the `Parent` components, then places every child relative
+/// to its parent. Runs before physics and before rendering.
+pub fn update(world: &mut World) {
+    sync_children(world);
+    propagate(world);
+}
+
+/// `Parent` is the source of truth; `Children` lists drop stale entries and gain new ones at the end.
+fn sync_children(world: &mut World) {
+    let mut empty = Vec::new();
+    for (entity, children) in world.query::<&mut Children>().iter() {
+        let mut seen = HashSet::new();
+        children.entities.retain(|child| child.get(world).is_some_and(|child| parent(world, child) == Some(entity) && seen.insert(child)));
+        if children.entities.is_empty() {
+            empty.push(entity);
+        }
+    }
+
+    let links: Vec<(Entity, Entity)> = world.query::<&Parent>().iter()
+        .filter_map(|(child, link)| link.entity.get(world).filter(|parent| *parent != child).map(|parent| (child, parent)))
+        .collect();
+    for (child, parent) in links {
+        add_child(world, parent, child);
+    }
+
+    for entity in empty {
+        if world.get::<&Children>(entity).is_ok_and(|children| children.entities.is_empty()) {
+            let _ = world.remove_one::<Children>(entity);
+        }
+    }
+}
+
+fn propagate(world: &mut World) {
+    let roots: Vec<Entity> = world.query::<&Children>().iter()
+        .map(|(entity, _)| entity)
+        .filter(|entity| parent(world, *entity).is_none())
+        .collect();
+
+    // Each child has a single parent, so walking down from the roots visits it once
+    let mut stack = roots;
+    while let Some(node) = stack.pop() {
+        let origin = world_pos(world, node);
+        let parent_transform = world_transform(world, node);
+        for child in children(world, node) {
+            if let Ok(link) = world.get::<&Parent>(child) {
+                if let Ok(mut pos) = world.get::<&mut Pos>(child) {
+                    let world_pos = origin + parent_transform.apply(vec2(link.x, link.y));
+                    pos.x = world_pos.x;
+                    pos.y = world_pos.y;
+                }
+                if let Ok(mut transform) = world.get::<&mut Transform>(child) {
+                    transform.rotation = parent_transform.rotation + link.rotation;
+                    transform.scale = [parent_transform.scale[0] * link.scale[0], parent_transform.scale[1] * link.scale[1]];
+                }
+            }
+            stack.push(child);
+        }
+    }
+}
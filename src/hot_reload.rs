@@ -0,0 +1,88 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use crate::systems::GameSystem;
+
+/// A rebuilt library is loaded only once it hasn't changed for this long,
+/// so a file cargo is still writing isn't picked up half-way.
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+const ENTRY_POINT: &[u8] = b"en_hot_systems";
+
+type EntryPoint = fn() -> Vec<&'static GameSystem>;
+
+#[derive(Debug)]
+pub enum HotReloadError {
+    Io(std::io::Error),
+    Load(libloading::Error),
+}
+
+impl fmt::Display for HotReloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HotReloadError::Io(err) => write!(f, "couldn't copy the library: {}", err),
+            HotReloadError::Load(err) => write!(f, "couldn't load the library: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for HotReloadError {}
+
+/// Watches the `game_systems` library and loads every new build of it.
+/// The world, resources and editor state stay in the executable, so only the code changes.
+pub struct HotReload {
+    path: PathBuf,
+    loaded: Option<SystemTime>,
+    /// Loaded copies are never unloaded: components, resources and event channels created by
+    /// their code keep pointers into them (drop glue, vtables, system names).
+    libraries: Vec<libloading::Library>,
+}
+
+impl HotReload {
+    /// Expects the library next to the executable, where cargo puts both.
+    pub fn new() -> Self {
+        let dir = std::env::current_exe().ok()
+            .and_then(|exe| exe.parent().map(|p| p.to_path_buf()))
+            .unwrap_or_default();
+        Self { path: dir.join(libloading::library_filename("game_systems")), loaded: None, libraries: Vec::new() }
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path).and_then(|m| m.modified()).ok()
+    }
+
+    /// Loads the current build and returns its systems.
+    pub fn load(&mut self) -> Result<Vec<&'static GameSystem>, HotReloadError> {
+        self.loaded = self.modified();
+
+        // The OS hands back the already-open library for a known path, so each build is loaded from its own copy
+        let file_name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        let copy = std::env::temp_dir().join(format!("en-{}-{}-{}", std::process::id(), self.libraries.len(), file_name));
+        std::fs::copy(&self.path, &copy).map_err(HotReloadError::Io)?;
+
+        // SAFETY: the library is built from this workspace against the same `en_dylib` (see `game_systems`)
+        let systems = unsafe {
+            let library = libloading::Library::new(&copy).map_err(HotReloadError::Load)?;
+            let entry: EntryPoint = *library.get::<EntryPoint>(ENTRY_POINT).map_err(HotReloadError::Load)?;
+            self.libraries.push(library);
+            entry()
+        };
+        let _ = std::fs::remove_file(&copy);
+        Ok(systems)
+    }
+
+    /// Returns the systems of a new build once it has settled on disk.
+    pub fn poll(&mut self) -> Option<Result<Vec<&'static GameSystem>, HotReloadError>> {
+        let modified = self.modified()?;
+        if Some(modified) == self.loaded || modified.elapsed().unwrap_or_default() < SETTLE_TIME {
+            return None;
+        }
+        Some(self.load())
+    }
+}
+
+impl Default for HotReload {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,534 @@
+use std::any::Any;
+use std::marker::PhantomData;
+use hecs::{CommandBuffer, Entity, World};
+use macroquad::prelude::*;
+pub use egui_macroquad::egui;
+use crate::components::Render;
+use crate::reflect::{components, ComponentInfo, FieldHints, FieldInfo};
+use crate::entity_ref::EntityRef;
+use crate::sprite_manager::{SpriteId, SpriteManager};
+
+/// What a field widget may need besides the value itself.
+pub struct WidgetCtx<'a> {
+    pub sprite_manager: &'a SpriteManager,
+    /// Unique per component field, for widgets that keep egui state.
+    pub id: egui::Id,
+    pub hints: &'a FieldHints,
+    /// For widgets that refer to other entities; components can't be borrowed through it while inspecting.
+    pub world: &'a World,
+}
+
+impl WidgetCtx<'_> {
+    /// Context for a value nested inside this one.
+    pub fn nested<'b>(&'b self, id: impl std::hash::Hash, hints: &'b FieldHints) -> WidgetCtx<'b> {
+        WidgetCtx { sprite_manager: self.sprite_manager, id: self.id.with(id), hints, world: self.world }
+    }
+}
+
+/// A value the inspector can edit. Implemented for the basic field types here and
+/// derived with `#[derive(engine_macros::Inspect)]` for plain structs and enums.
+pub trait Inspect {
+    /// Draws the editor and returns whether the value changed.
+    fn inspect(&mut self, ui: &mut egui::Ui, ctx: &WidgetCtx) -> bool;
+}
+
+/// Type-erased `Inspect::inspect`; `value` is always of the type the fn was made for.
+pub type InspectFn = fn(&mut dyn Any, &mut egui::Ui, &WidgetCtx) -> bool;
+
+/// Picks the editor of a field type without requiring `Inspect`, so generated code can handle
+/// any field: `(&Probe::<T>::new()).widget()` is `Some` through `ViaInspect` when `T: Inspect`
+/// and falls back to `ViaFallback` (`None`) otherwise. Both traits must be in scope.
+pub struct Probe<T>(PhantomData<T>);
+
+impl<T> Probe<T> {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+pub trait ViaInspect {
+    fn widget(&self) -> Option<InspectFn>;
+}
+
+impl<T: Inspect + 'static> ViaInspect for Probe<T> {
+    fn widget(&self) -> Option<InspectFn> {
+        Some(|value, ui, ctx| value.downcast_mut::<T>().unwrap().inspect(ui, ctx))
+    }
+}
+
+pub trait ViaFallback {
+    fn widget(&self) -> Option<InspectFn>;
+}
+
+impl<T> ViaFallback for &Probe<T> {
+    fn widget(&self) -> Option<InspectFn> {
+        None
+    }
+}
+
+/// Drag value honouring `#[range]` and `#[step]`. With a step the value snaps to multiples of it,
+/// dragging about four pixels per step.
+fn drag<T: egui::emath::Numeric>(ui: &mut egui::Ui, value: &mut T, hints: &FieldHints, prefix: &str) -> bool {
+    let mut drag = egui::DragValue::new(value).speed(hints.step.map_or(0.1, |step| step * 0.25)).prefix(prefix);
+    if let Some((min, max)) = hints.range {
+        drag = drag.range(T::from_f64(min)..=T::from_f64(max));
+    }
+    let changed = ui.add(drag).changed();
+    if changed && let Some(step) = hints.step {
+        *value = T::from_f64((value.to_f64() / step).round() * step);
+    }
+    changed
+}
+
+fn drag_value<T: egui::emath::Numeric>(ui: &mut egui::Ui, value: &mut T, ctx: &WidgetCtx) -> bool {
+    if ctx.hints.slider {
+        // A slider needs both ends; `#[slider]` without `#[range]` covers 0..1
+        let (min, max) = ctx.hints.range.unwrap_or((0.0, 1.0));
+        let mut slider = egui::Slider::new(value, T::from_f64(min)..=T::from_f64(max));
+        if let Some(step) = ctx.hints.step {
+            slider = slider.step_by(step);
+        }
+        return ui.add(slider).changed();
+    }
+    ui.scope(|ui| {
+        ui.spacing_mut().interact_size.x = ui.available_width();
+        drag(ui, value, ctx.hints, "")
+    }).inner
+}
+
+macro_rules! inspect_numbers {
+    ($($t:ty),*) => {
+        $(
+            impl Inspect for $t {
+                fn inspect(&mut self, ui: &mut egui::Ui, ctx: &WidgetCtx) -> bool {
+                    drag_value(ui, self, ctx)
+                }
+            }
+        )*
+    };
+}
+
+inspect_numbers!(f32, f64, i32, u32, usize, i64, u64);
+
+impl Inspect for String {
+    fn inspect(&mut self, ui: &mut egui::Ui, _ctx: &WidgetCtx) -> bool {
+        ui.add_sized(ui.available_size(), egui::TextEdit::singleline(self)).changed()
+    }
+}
+
+/// `[f32; 4]` fields are colors.
+impl Inspect for [f32; 4] {
+    fn inspect(&mut self, ui: &mut egui::Ui, _ctx: &WidgetCtx) -> bool {
+        ui.color_edit_button_rgba_unmultiplied(self).changed()
+    }
+}
+
+impl Inspect for [f32; 2] {
+    fn inspect(&mut self, ui: &mut egui::Ui, ctx: &WidgetCtx) -> bool {
+        ui.horizontal(|ui| {
+            let x = drag(ui, &mut self[0], ctx.hints, "X: ");
+            let y = drag(ui, &mut self[1], ctx.hints, "Y: ");
+            x || y
+        }).inner
+    }
+}
+
+impl Inspect for bool {
+    fn inspect(&mut self, ui: &mut egui::Ui, _ctx: &WidgetCtx) -> bool {
+        ui.checkbox(self, "").changed()
+    }
+}
+
+impl Inspect for SpriteId {
+    fn inspect(&mut self, ui: &mut egui::Ui, ctx: &WidgetCtx) -> bool {
+        sprite_picker(ui, self, ctx)
+    }
+}
+
+/// A dropdown of the scene's entities plus a button to pick the target by clicking it in the viewport.
+impl Inspect for EntityRef {
+    fn inspect(&mut self, ui: &mut egui::Ui, ctx: &WidgetCtx) -> bool {
+        let mut changed = false;
+        if let Some(entity) = take_picked(ui.ctx(), ctx.id) {
+            *self = EntityRef::new(entity);
+            changed = true;
+        }
+
+        ui.horizontal(|ui| {
+            let label = |entity: Option<Entity>| match entity {
+                Some(entity) if ctx.world.contains(entity) => format!("ID: {:?}", entity.id()),
+                Some(_) => "Missing".to_string(),
+                None => "None".to_string(),
+            };
+            egui::ComboBox::from_id_salt(ctx.id.with("target"))
+            .selected_text(label(self.0))
+            .show_ui(ui, |ui| {
+                changed |= ui.selectable_value(self, EntityRef::NONE, "None").changed();
+                for entity in ctx.world.iter().map(|e| e.entity()) {
+                    changed |= ui.selectable_value(self, EntityRef::new(entity), label(Some(entity))).changed();
+                }
+            });
+
+            let waiting = is_picking_for(ui.ctx(), ctx.id);
+            if ui.selectable_label(waiting, "🎯").on_hover_text("Pick in the viewport").clicked() {
+                set_pick(ui.ctx(), (!waiting).then_some(Pick::Waiting(ctx.id)));
+            }
+        });
+        changed
+    }
+}
+
+/// Viewport picking for `EntityRef` fields: the widget arms it, the editor's click handler
+/// completes it, and the widget takes the result on its next frame.
+#[derive(Clone, Copy)]
+enum Pick {
+    Waiting(egui::Id),
+    Picked(egui::Id, Entity),
+}
+
+fn pick_id() -> egui::Id {
+    egui::Id::new("entity_ref_pick")
+}
+
+fn set_pick(ctx: &egui::Context, pick: Option<Pick>) {
+    ctx.data_mut(|d| match pick {
+        Some(pick) => d.insert_temp(pick_id(), pick),
+        None => d.remove::<Pick>(pick_id()),
+    });
+}
+
+fn is_picking_for(ctx: &egui::Context, widget: egui::Id) -> bool {
+    matches!(ctx.data(|d| d.get_temp::<Pick>(pick_id())), Some(Pick::Waiting(id)) if id == widget)
+}
+
+fn take_picked(ctx: &egui::Context, widget: egui::Id) -> Option<Entity> {
+    let Some(Pick::Picked(id, entity)) = ctx.data(|d| d.get_temp::<Pick>(pick_id())) else {
+        return None;
+    };
+    if id != widget {
+        return None;
+    }
+    set_pick(ctx, None);
+    Some(entity)
+}
+
+/// Whether an `EntityRef` widget is waiting for a viewport click.
+pub fn is_picking(ctx: &egui::Context) -> bool {
+    matches!(ctx.data(|d| d.get_temp::<Pick>(pick_id())), Some(Pick::Waiting(_)))
+}
+
+/// Hands the entity clicked in the viewport to the waiting widget; `None` cancels the pick.
+pub fn finish_pick(ctx: &egui::Context, entity: Option<Entity>) {
+    if let Some(Pick::Waiting(widget)) = ctx.data(|d| d.get_temp::<Pick>(pick_id())) {
+        set_pick(ctx, entity.map(|entity| Pick::Picked(widget, entity)));
+    }
+}
+
+/// A checkbox that switches between `None` and `Some(T::default())`, next to the value's own editor.
+impl<T: Inspect + Default> Inspect for Option<T> {
+    fn inspect(&mut self, ui: &mut egui::Ui, ctx: &WidgetCtx) -> bool {
+        ui.horizontal(|ui| {
+            let mut enabled = self.is_some();
+            let mut changed = false;
+            if ui.checkbox(&mut enabled, "").changed() {
+                *self = enabled.then(T::default);
+                changed = true;
+            }
+            if let Some(value) = self {
+                changed |= value.inspect(ui, ctx);
+            }
+            changed
+        }).inner
+    }
+}
+
+/// One row per item with a remove button, plus a button adding `T::default()` at the end.
+/// The field's hints apply to every item.
+/// Lists taller than this scroll inside the inspector instead of growing it.
+const LIST_MAX_HEIGHT: f32 = 240.0;
+
+impl<T: Inspect + Default> Inspect for Vec<T> {
+    fn inspect(&mut self, ui: &mut egui::Ui, ctx: &WidgetCtx) -> bool {
+        let mut changed = false;
+        egui::CollapsingHeader::new(format!("{} items", self.len()))
+        .id_salt(ctx.id)
+        .show(ui, |ui| {
+            let mut remove = None;
+            // Only the rows scrolled into view are built, so long lists like tile maps stay cheap.
+            // Rows are assumed one widget high; taller items still show, the scrollbar is just less exact.
+            let row_height = ui.spacing().interact_size.y;
+            egui::ScrollArea::vertical()
+            .id_salt(ctx.id.with("scroll"))
+            .max_height(LIST_MAX_HEIGHT)
+            .show_rows(ui, row_height, self.len(), |ui, rows| {
+                egui::Grid::new(ctx.id.with("items")).num_columns(3).striped(true).show(ui, |ui| {
+                    for i in rows {
+                        ui.weak(i.to_string());
+                        changed |= self[i].inspect(ui, &ctx.nested(i, ctx.hints));
+                        if ui.small_button("❌").on_hover_text("Remove").clicked() {
+                            remove = Some(i);
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+            if let Some(i) = remove {
+                self.remove(i);
+                changed = true;
+            }
+            if ui.button("➕ Add").clicked() {
+                self.push(T::default());
+                changed = true;
+            }
+        });
+        changed
+    }
+}
+
+/// Collapsible group of field rows, used for nested structs and enum variants.
+pub fn group(ui: &mut egui::Ui, ctx: &WidgetCtx, title: &str, body: impl FnOnce(&mut egui::Ui, &WidgetCtx) -> bool) -> bool {
+    egui::CollapsingHeader::new(title)
+    .id_salt(ctx.id)
+    .default_open(true)
+    .show(ui, |ui| {
+        egui::Grid::new(ctx.id.with("fields"))
+        .num_columns(2)
+        .striped(true)
+        .show(ui, |ui| body(ui, ctx))
+        .inner
+    })
+    .body_returned
+    .unwrap_or(false)
+}
+
+/// Variant dropdown of a derived enum; returns the newly selected index.
+pub fn variant_picker(ui: &mut egui::Ui, ctx: &WidgetCtx, variants: &[&str], current: usize) -> Option<usize> {
+    let mut selected = current;
+    egui::ComboBox::from_id_salt(ctx.id.with("variant"))
+    .selected_text(variants[current])
+    .show_ui(ui, |ui| {
+        for (i, name) in variants.iter().enumerate() {
+            ui.selectable_value(&mut selected, i, *name);
+        }
+    });
+    (selected != current).then_some(selected)
+}
+
+fn field_label(ui: &mut egui::Ui, name: &str, hints: &FieldHints) -> egui::Response {
+    let label = ui.label(name);
+    match hints.tooltip {
+        Some(tooltip) => label.on_hover_text(tooltip),
+        None => label,
+    }
+}
+
+/// Draws `value` with `widget`, greyed out when readonly; unknown types are shown by name
+/// so missing widgets are easy to spot.
+fn field_value(ui: &mut egui::Ui, ctx: &WidgetCtx, widget: Option<InspectFn>, value: &mut dyn Any, type_name: &str) -> bool {
+    match widget {
+        Some(widget) => {
+            let edited = ui.add_enabled_ui(!ctx.hints.readonly, |ui| widget(value, ui, ctx)).inner;
+            edited && !ctx.hints.readonly
+        }
+        None => {
+            ui.weak(type_name);
+            false
+        }
+    }
+}
+
+/// One row of a nested struct or enum variant inside a `group`.
+pub fn field_row(ui: &mut egui::Ui, ctx: &WidgetCtx, name: &str, hints: &FieldHints, widget: Option<InspectFn>, value: &mut dyn Any, type_name: &str) -> bool {
+    if hints.hidden {
+        return false;
+    }
+    field_label(ui, name, hints);
+    let changed = field_value(ui, &ctx.nested(name, hints), widget, value, type_name);
+    ui.end_row();
+    changed
+}
+
+fn sprite_picker(ui: &mut egui::Ui, val: &mut SpriteId, ctx: &WidgetCtx) -> bool {
+    let sprite_manager = ctx.sprite_manager;
+    let mut sprite_changed = false;
+
+    ui.horizontal(|ui| {
+        ui.label("🖼");
+        let current_name = sprite_manager.sprite_names.get(&val.0)
+            .cloned()
+            .unwrap_or_else(|| "Unknown".to_string());
+
+        let picker_id = ctx.id.with("picker");
+        let search_id = picker_id.with("search");
+
+        let mut is_open = ui.data_mut(|d| d.get_temp::<bool>(picker_id).unwrap_or(false));
+
+        if ui.button(format!("{} ⏷", current_name)).clicked() {
+            is_open = true;
+        }
+
+        if is_open {
+            let mut open = is_open;
+            let mut should_close = false;
+
+            egui::Window::new("Select Sprite")
+            .open(&mut open)
+            .default_size([450.0, 350.0])
+            .collapsible(true)
+            .vscroll(false)
+            .show(ui.ctx(), |ui| {
+
+                let mut search_text = ui.data_mut(|d| d.get_temp::<String>(search_id).unwrap_or_default());
+                ui.horizontal(|ui| {
+                    ui.label("🔍");
+                    ui.text_edit_singleline(&mut search_text);
+                    if ui.button("✖").clicked() { search_text.clear(); }
+                });
+                ui.separator();
+
+                let mut names: Vec<_> = sprite_manager.sprite_names.iter().collect();
+                names.sort_by(|a, b| a.1.cmp(b.1));
+
+                egui::ScrollArea::vertical().auto_shrink([false; 2]).show(ui, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                ui.spacing_mut().item_spacing = egui::vec2(10.0, 10.0);
+                for (hash_id, name) in names {
+                    if search_text.is_empty() || name.to_lowercase().contains(&search_text.to_lowercase()) {
+                        ui.allocate_ui(egui::vec2(110.0, 130.0), |ui| {
+                        ui.vertical_centered(|ui| {
+
+                        if let Some(sprite) = sprite_manager.sprites.get(hash_id) {
+                            let miniquad_id = sprite.texture.raw_miniquad_id();
+
+                            let raw_handle_u32 = unsafe {
+                                let internal_gl = get_internal_gl();
+                                let raw_id = internal_gl.quad_context.texture_raw_id(miniquad_id);
+                                std::mem::transmute::<_, u32>(raw_id)
+                            };
+                            let egui_texture_id = egui::TextureId::User(raw_handle_u32 as u64);
+
+                            let tw = sprite.texture.width();
+                            let th = sprite.texture.height();
+
+                            let uv = egui::Rect::from_min_max(
+                                egui::pos2(sprite.source_rect.x / tw, sprite.source_rect.y / th),
+                                egui::pos2((sprite.source_rect.x + sprite.source_rect.w) / tw, (sprite.source_rect.y + sprite.source_rect.h) / th),
+                            );
+
+                            let (rect, response) = ui.allocate_exact_size(egui::vec2(100.0, 100.0), egui::Sense::click());
+
+                            ui.painter().image(egui_texture_id, rect, uv, egui::Color32::WHITE);
+
+                            if response.hovered() {
+                                ui.painter().rect_stroke(rect, 0.0, (2.0, egui::Color32::WHITE), egui::StrokeKind::Inside);
+                            }
+
+                            let is_selected = val.0 == *hash_id;
+                            if is_selected {
+                                ui.painter().rect_stroke(rect, 0.0, (3.0, egui::Color32::GREEN), egui::StrokeKind::Inside);
+                            }
+
+                            if response.clicked() {
+                                val.0 = *hash_id;
+                                sprite_changed = true;
+                                should_close = true;
+                            }
+                        }
+
+                        ui.add(egui::Label::new(
+                            egui::RichText::new(name).size(12.0)
+                        ).truncate());
+                        });
+                        });
+                    }
+                }
+                });
+                });
+
+                ui.data_mut(|d| d.insert_temp(search_id, search_text));
+            });
+            if should_close {
+                open = false;
+            }
+            ui.data_mut(|d| d.insert_temp(picker_id, open));
+        }
+    });
+    sprite_changed
+}
+
+/// Draws one component field, with a context menu to reset it to its declared default.
+fn draw_field(ui: &mut egui::Ui, world: &World, comp: &mut dyn Any, info: &ComponentInfo, field: &FieldInfo, sprite_manager: &SpriteManager) -> bool {
+    let hints = &field.hints;
+    let label = field_label(ui, field.name, hints);
+
+    let mut changed = false;
+    if !hints.readonly {
+        label.context_menu(|ui| {
+            if ui.button("↺ Reset to default").clicked() {
+                (field.reset)(comp);
+                changed = true;
+                ui.close_menu();
+            }
+        });
+    }
+
+    let ctx = WidgetCtx { sprite_manager, id: ui.make_persistent_id((info.name, field.name)), hints, world };
+    changed |= field_value(ui, &ctx, (field.inspect)(), (field.get_mut)(comp), field.type_name);
+    changed
+}
+
+pub fn draw_component_menu(ui: &mut egui::Ui, cmd: &mut CommandBuffer, entity: Entity) {
+    ui.menu_button("➕ Add component", |ui| {
+        for info in components() {
+            if ui.button(info.name).clicked() {
+                (info.insert_default)(cmd, entity);
+                ui.close_menu();
+            }
+        }
+    });
+}
+
+pub fn draw_entity_inspector(ui: &mut egui::Ui, world: &World, cmd: &mut CommandBuffer, entity: Entity, sprite_manager: &SpriteManager) {
+    for info in components() {
+        if !(info.has)(world, entity) {
+            continue;
+        }
+        let id = ui.make_persistent_id(info.name);
+        let mut remove_clicked = false;
+
+        egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), id, true)
+        .show_header(ui, |ui| {
+            ui.strong(info.name);
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.button("❌").on_hover_text("Remove").clicked() {
+                    remove_clicked = true;
+                }
+            });
+        })
+        .body(|ui| {
+            (info.with_mut)(world, entity, &mut |comp| {
+                let mut changed = false;
+                egui::Grid::new(info.name)
+                .num_columns(2)
+                .spacing([40.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    for field in info.fields.iter().filter(|f| !f.hints.hidden) {
+                        changed |= draw_field(ui, world, comp, info, field, sprite_manager);
+                        ui.end_row();
+                    }
+                });
+
+                // The cached sprite goes stale when the sprite id or anything it depends on changes
+                if changed && let Some(render) = comp.downcast_mut::<Render>() {
+                    render.cached_sprite = None;
+                }
+            });
+        });
+        ui.add_space(4.0);
+        if remove_clicked {
+            (info.remove)(cmd, entity);
+        }
+    }
+}
@@ -0,0 +1,24 @@
+pub mod en;
+pub mod components;
+pub mod sprite_manager;
+pub mod editor;
+pub mod physics;
+pub mod aseprite;
+pub mod systems;
+pub mod render;
+pub mod resources;
+pub mod events;
+pub mod state;
+pub mod profiler;
+pub mod scripting;
+pub mod reflect;
+pub mod scene;
+pub mod migrate;
+pub mod scene_manager;
+pub mod entity_ref;
+pub mod hierarchy;
+pub mod prefab;
+#[cfg(debug_assertions)]
+pub mod inspector;
+#[cfg(feature = "hot-reload")]
+pub mod hot_reload;
@@ -1,14 +1,6 @@
-use en::*;
-
-pub mod en;
-mod components;
-mod macros;
-mod sprite_manager;
-mod editor;
-mod physics;
-mod aseprite;
-mod systems;
-mod render;
+use en::en::*;
+use en::{hierarchy, physics, profiler, render, scene_manager, state, systems};
+#[cfg(debug_assertions)] use en::editor;
 
 fn window_conf() -> Conf {
     Conf {
@@ -20,23 +12,52 @@ fn window_conf() -> Conf {
     }
 }
 
+/// The `GameState` the game starts in; its `on_enter` systems run before the first frame.
+const INITIAL_STATE: &str = "Playing";
+
 #[macroquad::main(window_conf)]
 async fn main() {
+    #[cfg(feature = "hot-reload")]
+    let mut hot_reload = en::hot_reload::HotReload::new();
+    #[cfg(feature = "hot-reload")]
+    let mut hot_systems = hot_reload.load().unwrap_or_else(|err| panic!("Failed to load game_systems (build it with `cargo build -p game_systems --features en/hot-reload`): {}", err));
+    #[cfg(not(feature = "hot-reload"))]
+    let hot_systems = Vec::new();
+
     let mut world = World::new();
+    let mut resources = Resources::new();
+    let mut events = Events::new();
+    let mut commands = Commands::new();
+    resources.insert(RunState::default());
+    resources.insert(GameState::new(INITIAL_STATE));
+    resources.insert(profiler::Profiler::default());
+    let mut script_host = ScriptHost::new();
+    script_host.load_all();
+    resources.insert(script_host);
+
+    let mut schedule = build_schedule(&hot_systems, &resources.expect::<ScriptHost>()).unwrap_or_else(|err| panic!("Failed to build system schedule: {}", err));
+
     let mut sprite_manager = SpriteManager::new();
     sprite_manager.load_all("assets/sprites").await;
 
-    if let Ok(bytes) = macroquad::file::load_file("Scene.bin").await {
-        load_scene(&mut world, &bytes);
-        for (_id, ren) in world.query_mut::<&mut Render>() {
-            ren.cached_sprite = None;
-        }
+    let mut scenes = SceneManager::default();
+    let scene_loaded = scene_manager::load_start(&mut world, &mut scenes).await;
+    if scene_loaded {
         println!("Scene loaded successfully!");
-    } else {
-        println!("Scene.bin not found or failed to load.");
     }
+    resources.insert(scenes);
+
+    {
+        let mut ctx = systems::SysCtx { world: &mut world, sprites: &mut sprite_manager, resources: &mut resources, events: &mut events, commands: &mut commands, dt: 0.0, alpha: 1.0 };
+        systems::run_all_systems(&schedule, systems::Stage::Startup, &mut ctx);
+        if scene_loaded {
+            systems::run_all_systems(&schedule, systems::Stage::OnSceneLoaded, &mut ctx);
+        }
+        state::apply_transition(&schedule, &mut ctx);
+    }
+    prevent_quit();
 
-    let mut is_paused = false;
+    let mut fixed_time = systems::FixedTime::new(60.0, 5);
     let mut camera_zoom = 1.0;
     let mut camera_free_pos = vec2(0.0, 0.0);
 
@@ -48,20 +69,69 @@ async fn main() {
     #[cfg(debug_assertions)] let mut ctx_menu_world: Option<Vec2> = None;
     #[cfg(debug_assertions)] let mut ctx_menu_screen: Option<Vec2> = None;
     #[cfg(debug_assertions)] let mut brush_mode = false;
+    #[cfg(debug_assertions)] let mut scene_generation = resources.expect::<SceneManager>().generation();
 
     loop {
         clear_background(DARKBLUE);
         let dt = get_frame_time().min(0.1);
 
+        #[allow(unused_mut)]
+        let mut rebuild = resources.expect_mut::<ScriptHost>().take_systems_changed();
+        #[cfg(feature = "hot-reload")]
+        match hot_reload.poll() {
+            Some(Ok(systems)) => {
+                hot_systems = systems;
+                rebuild = true;
+                println!("Reloaded game_systems");
+            }
+            Some(Err(err)) => println!("Failed to reload game_systems: {}", err),
+            None => {}
+        }
+        if rebuild {
+            match build_schedule(&hot_systems, &resources.expect::<ScriptHost>()) {
+                Ok(mut rebuilt) => {
+                    rebuilt.keep_settings(&schedule);
+                    schedule = rebuilt;
+                }
+                Err(err) => println!("Kept the old systems: {}", err),
+            }
+        }
+
+        #[cfg(debug_assertions)] { resources.expect_mut::<RunState>().editor_open = show_editor; }
+        let is_paused = resources.expect::<RunState>().paused;
+
         // 1. UPDATE 
-        if !is_paused {
-            systems::run_all_systems(&mut systems::SysCtx { world: &mut world, sprites: &mut sprite_manager, dt });
-            physics::update_physics(&mut world, dt);
+        // Fixed steps keep ticking while paused for `while_paused` systems; the rest are skipped per system
+        let steps = fixed_time.advance(dt);
+        let fixed_dt = fixed_time.step_dt();
+        for _ in 0..steps {
+            events.update_fixed();
+            if !is_paused {
+                hierarchy::update(&mut world);
+                physics::snapshot_positions(&mut world);
+            }
+            let mut ctx = systems::SysCtx { world: &mut world, sprites: &mut sprite_manager, resources: &mut resources, events: &mut events, commands: &mut commands, dt: fixed_dt, alpha: 0.0 };
+            systems::run_fixed_systems(&schedule, systems::Stage::PreUpdate, &mut ctx);
+            systems::run_fixed_systems(&schedule, systems::Stage::Update, &mut ctx);
+            hierarchy::update(ctx.world);
+            if !is_paused {
+                let ((), physics_ms) = profiler::timed(|| physics::update_physics(ctx.world, ctx.events, fixed_dt));
+                ctx.resources.expect_mut::<profiler::Profiler>().record("update_physics", physics_ms);
+            }
+            systems::run_fixed_systems(&schedule, systems::Stage::PostUpdate, &mut ctx);
         }
+        // While paused the editor moves entities directly, so draw them where they are.
+        let alpha = if is_paused { 1.0 } else { fixed_time.alpha() };
+
+        // Frame-rate systems decide for themselves whether to run while paused (see `while_paused`)
+        let mut ctx = systems::SysCtx { world: &mut world, sprites: &mut sprite_manager, resources: &mut resources, events: &mut events, commands: &mut commands, dt, alpha };
+        systems::run_all_systems(&schedule, systems::Stage::PreUpdate, &mut ctx);
+        systems::run_all_systems(&schedule, systems::Stage::Update, &mut ctx);
+        systems::run_all_systems(&schedule, systems::Stage::PostUpdate, &mut ctx);
 
         // 2. CAMERA 
         let (final_cam_pos, final_zoom) = update_camera_logic(
-            &world, dt, &mut camera_free_pos, &mut camera_zoom, 
+            &world, dt, alpha, &mut camera_free_pos, &mut camera_zoom, 
             #[cfg(debug_assertions)] show_editor, 
             #[cfg(debug_assertions)] block_editor_input
         );
@@ -83,12 +153,15 @@ async fn main() {
         }
 
         // 4. RENDER WORLD 
-        render::render_world(
-            &mut world, &mut sprite_manager, &camera, final_zoom,
+        hierarchy::update(&mut world);
+        let ((), render_ms) = profiler::timed(|| render::render_world(
+            &mut world, &mut sprite_manager, &camera, final_zoom, alpha,
             #[cfg(debug_assertions)] show_editor,
             #[cfg(debug_assertions)] brush_mode,
             #[cfg(debug_assertions)] selected_entity
-        );
+        ));
+        resources.expect_mut::<profiler::Profiler>().record("render_world", render_ms);
+        systems::run_all_systems(&schedule, systems::Stage::Render, &mut systems::SysCtx { world: &mut world, sprites: &mut sprite_manager, resources: &mut resources, events: &mut events, commands: &mut commands, dt, alpha });
 
         set_default_camera();
 
@@ -98,28 +171,56 @@ async fn main() {
             if is_key_pressed(KeyCode::Tab) { show_editor = !show_editor; }
             if show_editor {
                 editor::draw_editor(
-                    &mut world, &mut selected_entity, &mut is_paused, 
+                    &mut world, &mut selected_entity, &mut resources.expect_mut::<RunState>().paused, 
                     &mut block_editor_input, &mut ctx_menu_world, 
-                    &mut ctx_menu_screen, &sprite_manager 
+                    &mut ctx_menu_screen, &sprite_manager, &mut events,
+                    &mut resources.expect_mut::<SceneManager>(), &mut schedule, &resources.expect::<profiler::Profiler>()
                 );
                 egui_macroquad::draw();
             }
         }
 
+        if is_quit_requested() {
+            systems::run_all_systems(&schedule, systems::Stage::Shutdown, &mut systems::SysCtx { world: &mut world, sprites: &mut sprite_manager, resources: &mut resources, events: &mut events, commands: &mut commands, dt: 0.0, alpha: 1.0 });
+            break;
+        }
+
+        let mut ctx = systems::SysCtx { world: &mut world, sprites: &mut sprite_manager, resources: &mut resources, events: &mut events, commands: &mut commands, dt: 0.0, alpha: 1.0 };
+        scene_manager::apply_requests(&schedule, &mut ctx).await;
+        state::apply_transition(&schedule, &mut ctx);
+        // Scenes reuse entity indices, so handles into a replaced world would point at new entities
+        #[cfg(debug_assertions)]
+        if resources.expect::<SceneManager>().generation() != scene_generation {
+            scene_generation = resources.expect::<SceneManager>().generation();
+            selected_entity = None;
+            dragging_entity = None;
+        }
+        events.update();
+        resources.expect_mut::<profiler::Profiler>().end_frame(get_frame_time() * 1000.0);
         next_frame().await;
     }
 }
 
+/// Builds the schedule from the registered, hot-reloaded and script systems. Scripts are edited
+/// while the game runs, so when theirs don't fit the schedule it's built without them instead.
+fn build_schedule(hot_systems: &[&'static systems::GameSystem], scripts: &ScriptHost) -> Result<systems::Schedule, systems::ScheduleError> {
+    let all = [hot_systems, scripts.systems()].concat();
+    systems::Schedule::build_with(&all).or_else(|err| {
+        println!("cargo:warning=Leaving out script systems: {}", err);
+        systems::Schedule::build_with(hot_systems)
+    })
+}
+
 fn update_camera_logic(
-    world: &World, dt: f32, free_pos: &mut Vec2, free_zoom: &mut f32, 
+    world: &World, dt: f32, alpha: f32, free_pos: &mut Vec2, free_zoom: &mut f32, 
     #[cfg(debug_assertions)] show_editor: bool, 
     #[cfg(debug_assertions)] block_input: bool
 ) -> (Vec2, f32) {
     let mut game_pos = vec2(0.0, 0.0);
     let mut game_zoom = 1.0;
     
-    for (_id, (pos, cam)) in world.query::<(&Pos, &CameraAnchor)>().iter() {
-        game_pos = vec2(pos.x, pos.y);
+    for (_id, (pos, cam, prev)) in world.query::<(&Pos, &CameraAnchor, Option<&physics::PrevPos>)>().iter() {
+        game_pos = render::lerp_pos(pos, prev, alpha);
         game_zoom = cam.zoom;
     }
 
@@ -0,0 +1,168 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::OnceLock;
+use serde_json::Value;
+use crate::reflect;
+use crate::scene::{Scene, SceneError};
+
+/// Upgrades one saved component from version `from` to `from + 1`. Registered by `#[migration]`.
+pub struct Migration {
+    pub component: &'static str,
+    pub from: u32,
+    pub name: &'static str,
+    pub apply: fn(&mut Migrate),
+}
+
+inventory::collect!(Migration);
+
+/// Expanded by `#[migration]`. The hot-reload library (`game_systems`) defines its own
+/// version that leaves the engine's registry alone.
+macro_rules! register_migration {
+    ($migration:expr) => {
+        inventory::submit! { $migration }
+    };
+}
+pub(crate) use register_migration;
+
+fn migration(component: &str, from: u32) -> Option<&'static Migration> {
+    static MIGRATIONS: OnceLock<HashMap<(&'static str, u32), &'static Migration>> = OnceLock::new();
+    MIGRATIONS.get_or_init(|| {
+        let mut migrations = HashMap::new();
+        for migration in inventory::iter::<Migration> {
+            if migrations.insert((migration.component, migration.from), migration).is_some() {
+                println!("cargo:warning=More than one migration for {} version {}", migration.component, migration.from);
+            }
+        }
+        migrations
+    }).get(&(component, from)).copied()
+}
+
+/// One saved component on its way to the current layout.
+pub struct Migrate {
+    /// The saved fields, as they are in scene files.
+    pub value: Value,
+    removed: bool,
+    added: Vec<(String, u32, Value)>,
+}
+
+impl Migrate {
+    /// Removes a field and returns its saved value.
+    pub fn take(&mut self, field: &str) -> Option<Value> {
+        self.value.as_object_mut()?.remove(field)
+    }
+
+    pub fn set(&mut self, field: &str, value: Value) {
+        if let Some(fields) = self.value.as_object_mut() {
+            fields.insert(field.to_string(), value);
+        }
+    }
+
+    pub fn rename(&mut self, from: &str, to: &str) {
+        if let Some(value) = self.take(from) {
+            self.set(to, value);
+        }
+    }
+
+    /// Drops this component from the entity, e.g. once its fields were moved with `add`.
+    pub fn remove(&mut self) {
+        self.removed = true;
+    }
+
+    /// Gives the entity another component. `value` is in the layout of `version` of that
+    /// component and goes through its own migrations from there.
+    pub fn add(&mut self, component: &str, version: u32, value: Value) {
+        self.added.push((component.to_string(), version, value));
+    }
+}
+
+/// What `upgrade` changed, for tools to print.
+#[derive(Default)]
+pub struct MigrationReport {
+    /// Number of saved components moved past each version, with the migration that did it
+    /// (`None` when a version was raised without one).
+    pub steps: BTreeMap<(String, u32), (Option<&'static str>, usize)>,
+}
+
+impl MigrationReport {
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+impl fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for ((component, from), (name, count)) in &self.steps {
+            write!(f, "{} {} -> {}", component, from, from + 1)?;
+            if let Some(name) = name {
+                write!(f, " ({})", name)?;
+            }
+            writeln!(f, ": {} saved", count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Brings every saved component up to the version this build registers. Components that are
+/// no longer registered go through whatever migrations still exist for them and stay otherwise,
+/// so loading reports them instead of dropping their data.
+pub fn upgrade(scene: &mut Scene) -> Result<MigrationReport, SceneError> {
+    for (name, version) in &scene.versions {
+        if let Some(info) = reflect::component(name) && *version > info.version {
+            return Err(SceneError::Newer { what: name.clone(), saved: *version, supported: info.version });
+        }
+    }
+
+    let mut report = MigrationReport::default();
+    let mut queue: Vec<(String, u32, u64, Value)> = Vec::new();
+    for (name, saved) in std::mem::take(&mut scene.components) {
+        let version = scene.versions.get(&name).copied().unwrap_or(0);
+        queue.extend(saved.into_iter().map(|(id, value)| (name.clone(), version, id, value)));
+    }
+
+    let mut unknown_versions = BTreeMap::new();
+    while let Some((name, mut version, id, value)) = queue.pop() {
+        let current = reflect::component(&name).map(|info| info.version);
+        if let Some(current) = current && version > current {
+            return Err(SceneError::Newer { what: name, saved: version, supported: current });
+        }
+
+        let mut migrate = Migrate { value, removed: false, added: Vec::new() };
+        while current.is_none_or(|current| version < current) {
+            let migration = migration(&name, version);
+            if migration.is_none() && current.is_none() {
+                break;
+            }
+            if let Some(migration) = migration {
+                (migration.apply)(&mut migrate);
+            }
+            report.steps.entry((name.clone(), version)).or_insert((migration.map(|m| m.name), 0)).1 += 1;
+            version += 1;
+            queue.extend(migrate.added.drain(..).map(|(component, version, value)| (component, version, id, value)));
+            if migrate.removed {
+                break;
+            }
+        }
+
+        if !migrate.removed {
+            if current.is_none() {
+                unknown_versions.insert(name.clone(), version);
+            }
+            scene.components.entry(name).or_default().push((id, migrate.value));
+        }
+    }
+
+    for saved in scene.components.values_mut() {
+        saved.sort_by_key(|(id, _)| *id);
+    }
+    scene.versions = current_versions();
+    scene.versions.extend(unknown_versions.into_iter().filter(|(_, version)| *version > 0));
+    Ok(report)
+}
+
+/// Version of every registered component that has one, as recorded in saved scenes.
+pub fn current_versions() -> BTreeMap<String, u32> {
+    reflect::components().iter()
+        .filter(|info| info.version > 0)
+        .map(|info| (info.name.to_string(), info.version))
+        .collect()
+}
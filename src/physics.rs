@@ -1,13 +1,42 @@
-use crate::components::{Pos, Collider, Vel};
+use crate::components::{Pos, Collider, Transform, Vel};
+use crate::events::{Event, Events};
+use crate::hierarchy::Parent;
 use macroquad::prelude::Rect;
 
-pub fn update_physics(world: &mut hecs::World, dt: f32) {
+/// Sent when the moving collider `a` is pushed out of `b`.
+#[allow(dead_code)]
+pub struct Collision { pub a: hecs::Entity, pub b: hecs::Entity }
+
+impl Event for Collision {}
+
+/// Position at the start of the last fixed step, used by rendering to interpolate.
+/// Runtime only: added to every moving entity and every child by `snapshot_positions` and never
+/// saved. Children need it too, or they would be drawn at their parent's latest position while
+/// the parent itself is interpolated.
+pub struct PrevPos { pub x: f32, pub y: f32 }
+
+/// Expects children to be placed already (`hierarchy::update`), so they record where they were drawn.
+pub fn snapshot_positions(world: &mut hecs::World) {
+    let missing: Vec<hecs::Entity> = world
+        .query::<hecs::Without<(&Pos, hecs::Or<&Vel, &Parent>), &PrevPos>>()
+        .iter()
+        .map(|(id, _)| id)
+        .collect();
+    for id in missing {
+        world.insert_one(id, PrevPos { x: 0.0, y: 0.0 }).unwrap();
+    }
+
+    for (_id, (pos, prev)) in world.query_mut::<(&Pos, &mut PrevPos)>() {
+        prev.x = pos.x;
+        prev.y = pos.y;
+    }
+}
+
+pub fn update_physics(world: &mut hecs::World, events: &mut Events, dt: f32) {
     let colliders: Vec<(hecs::Entity, Rect, bool)> = world
-        .query::<(&Pos, &Collider)>()
+        .query::<(&Pos, &Collider, Option<&Transform>)>()
         .iter()
-        .map(|(id, (pos, col))| {
-            (id, Rect::new(pos.x - col.w / 2.0, pos.y - col.h / 2.0, col.w, col.h), col.is_static)
-        })
+        .map(|(id, (pos, col, transform))| (id, col.rect(pos, transform), col.is_static))
         .collect();
 
     for (_id, (pos, vel)) in world.query_mut::<(&mut Pos, &mut Vel)>() {
@@ -17,15 +46,17 @@ pub fn update_physics(world: &mut hecs::World, dt: f32) {
         vel.y -= vel.y * vel.d * dt;
     }
 
-    for (id_a, (pos_a, col_a, vel_a)) in world.query_mut::<(&mut Pos, &Collider, &mut Vel)>() {
+    for (id_a, (pos_a, col_a, vel_a, transform_a)) in world.query_mut::<(&mut Pos, &Collider, &mut Vel, Option<&Transform>)>() {
         if col_a.is_static { continue; }
 
-        let rect_a = Rect::new(pos_a.x - col_a.w / 2.0, pos_a.y - col_a.h / 2.0, col_a.w, col_a.h);
+        let rect_a = col_a.rect(pos_a, transform_a);
 
         for (id_b, rect_b, _) in &colliders {
             if id_a == *id_b { continue; }
 
             if let Some(overlap) = rect_a.intersect(*rect_b) {
+                events.send(Collision { a: id_a, b: *id_b });
+
                 let center_a_x = rect_a.x + rect_a.w / 2.0;
                 let center_b_x = rect_b.x + rect_b.w / 2.0;
                 let center_a_y = rect_a.y + rect_a.h / 2.0;
@@ -0,0 +1,421 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use hecs::{CommandBuffer, Entity, World};
+use engine_macros::component;
+use serde_json::Value;
+use crate::entity_ref::{self, EntityRef};
+use crate::hierarchy;
+use crate::reflect;
+use crate::scene::{Scene, SceneError, SceneFormat};
+
+pub const PREFABS_DIR: &str = "assets/prefabs";
+
+/// Component name of `PrefabInstance` in saved scenes.
+pub const INSTANCE: &str = "PrefabInstance";
+
+/// How often prefab files are checked for changes, in seconds.
+const RELOAD_INTERVAL: f32 = 0.5;
+
+/// Links an entity to the prefab entity it was made from. Scenes save only the fields that
+/// differ from the prefab, so editing the prefab file updates every instance.
+#[component]
+pub struct PrefabInstance {
+    #[readonly]
+    #[tooltip("File in assets/prefabs")]
+    pub prefab: String,
+    #[readonly]
+    #[tooltip("Root entity of this instance")]
+    pub root: EntityRef,
+    /// Id of the entity inside the prefab file.
+    #[hidden]
+    pub source: u64,
+    /// Components of the prefab entity this instance doesn't have, as of the last save.
+    #[hidden]
+    pub removed: Vec<String>,
+}
+
+/// `PrefabInstance` as it appears in a saved scene, before entity ids are remapped.
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+struct Link {
+    prefab: String,
+    root: Option<u64>,
+    source: u64,
+    removed: Vec<String>,
+}
+
+struct LoadedPrefab {
+    scene: Arc<Scene>,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Default)]
+struct Prefabs {
+    loaded: HashMap<String, LoadedPrefab>,
+    since_check: f32,
+}
+
+/// Prefabs read so far, shared by scene loading, saving and the editor.
+static PREFABS: Mutex<Option<Prefabs>> = Mutex::new(None);
+
+fn with_prefabs<R>(f: impl FnOnce(&mut Prefabs) -> R) -> R {
+    f(PREFABS.lock().unwrap().get_or_insert_with(Prefabs::default))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn read(name: &str) -> Result<LoadedPrefab, SceneError> {
+    let path = Path::new(PREFABS_DIR).join(name);
+    let modified = modified(&path);
+    let error = |error| SceneError::Prefab { prefab: name.to_string(), error: Box::new(error) };
+    let data = std::fs::read(&path).map_err(|err| error(SceneError::Io(err)))?;
+    let mut scene = Scene::decode(&data, SceneFormat::from_path(&path)).map_err(error)?;
+    crate::migrate::upgrade(&mut scene).map_err(error)?;
+    if let Some(name) = scene.components.keys().find(|name| reflect::component(name).is_none()) {
+        return Err(error(SceneError::UnknownComponent(name.clone())));
+    }
+    Ok(LoadedPrefab { scene: Arc::new(scene), modified })
+}
+
+/// The prefab as last read from disk.
+pub fn load(name: &str) -> Result<Arc<Scene>, SceneError> {
+    if let Some(prefab) = with_prefabs(|prefabs| prefabs.loaded.get(name).map(|prefab| prefab.scene.clone())) {
+        return Ok(prefab);
+    }
+    let prefab = read(name)?;
+    let scene = prefab.scene.clone();
+    with_prefabs(|prefabs| prefabs.loaded.insert(name.to_string(), prefab));
+    Ok(scene)
+}
+
+/// Prefab files in `assets/prefabs`, sorted.
+pub fn list() -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(PREFABS_DIR)
+        .map(|entries| entries.filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .filter(|name| name.ends_with(".json") || name.ends_with(".bin"))
+            .collect())
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+/// The prefab entity that has no parent inside the prefab.
+fn root(prefab: &Scene) -> Option<u64> {
+    let children: HashSet<u64> = prefab.components.get("Parent")
+        .map(|saved| saved.iter().map(|(id, _)| *id).collect())
+        .unwrap_or_default();
+    prefab.entities().into_iter().find(|id| !children.contains(id))
+}
+
+/// Scene entity and link of one instance, keyed by the prefab entity they stand for.
+type Members = BTreeMap<u64, (u64, Link)>;
+
+/// The instances in a scene: for each instance root, which scene entity stands for which
+/// prefab entity. An entity claiming a prefab entity that another one of the same instance
+/// already has (e.g. a copied child) is left out and saved like any other entity.
+fn instances(scene: &Scene) -> BTreeMap<u64, (String, Members)> {
+    let mut instances: BTreeMap<u64, (String, Members)> = BTreeMap::new();
+    for (id, value) in scene.components.get(INSTANCE).into_iter().flatten() {
+        let Ok(link) = serde_json::from_value::<Link>(value.clone()) else {
+            continue;
+        };
+        let (prefab, members) = instances.entry(link.root.unwrap_or(*id)).or_insert_with(|| (link.prefab.clone(), BTreeMap::new()));
+        if link.prefab == *prefab && !members.contains_key(&link.source) {
+            members.insert(link.source, (*id, link));
+        }
+    }
+    instances
+}
+
+/// Prefab components in the id space of the scene, given which scene entity each prefab entity
+/// is. Prefab entities without one are left out.
+fn prefab_values(prefab: &Scene, ids: &HashMap<u64, u64>) -> Result<BTreeMap<(u64, String), Value>, SceneError> {
+    let remap: HashMap<u64, Entity> = ids.iter().filter_map(|(source, id)| Some((*source, Entity::from_bits(*id)?))).collect();
+    entity_ref::with_remap(&remap, false, || {
+        let mut values = BTreeMap::new();
+        for (name, saved) in prefab.components.iter().filter(|(name, _)| name.as_str() != INSTANCE) {
+            let info = reflect::component(name).ok_or_else(|| SceneError::UnknownComponent(name.clone()))?;
+            for (source, value) in saved {
+                let Some(id) = ids.get(source) else {
+                    continue;
+                };
+                let value = (info.normalize)(value.clone())
+                    .map_err(|message| SceneError::Field { component: name.clone(), entity: *source, message })?;
+                values.insert((*id, name.clone()), value);
+            }
+        }
+        Ok(values)
+    })
+}
+
+fn index(scene: &Scene) -> HashMap<(u64, String), usize> {
+    scene.components.iter()
+        .flat_map(|(name, saved)| saved.iter().enumerate().map(move |(i, (id, _))| ((*id, name.clone()), i)))
+        .collect()
+}
+
+/// Replaces the saved components of prefab instances with what differs from the prefab:
+/// changed fields, components the prefab doesn't have, and (in `PrefabInstance`) the ones
+/// it has that were removed. Instances of prefabs that can't be read are saved whole.
+pub fn strip(scene: &mut Scene) {
+    strip_with(scene, &mut load);
+}
+
+fn strip_with(scene: &mut Scene, load: &mut dyn FnMut(&str) -> Result<Arc<Scene>, SceneError>) {
+    for (prefab_name, members) in instances(scene).into_values() {
+        let prefab = match load(&prefab_name) {
+            Ok(prefab) => prefab,
+            Err(err) => {
+                println!("Saving instances of {} whole: {}", prefab_name, err);
+                continue;
+            }
+        };
+        let ids: HashMap<u64, u64> = members.iter().map(|(source, (id, _))| (*source, *id)).collect();
+        let base = match prefab_values(&prefab, &ids) {
+            Ok(base) => base,
+            Err(err) => {
+                println!("Saving instances of {} whole: {}", prefab_name, err);
+                continue;
+            }
+        };
+
+        let mut removed: HashMap<u64, Vec<String>> = HashMap::new();
+        let index = index(scene);
+        let mut emptied = HashSet::new();
+        for ((id, name), base) in &base {
+            let Some(&i) = index.get(&(*id, name.clone())) else {
+                removed.entry(*id).or_default().push(name.clone());
+                continue;
+            };
+            let saved = &mut scene.components.get_mut(name).unwrap()[i].1;
+            if let (Some(fields), Some(base)) = (saved.as_object_mut(), base.as_object()) {
+                fields.retain(|field, value| base.get(field) != Some(value));
+            }
+            if saved == base || saved.as_object().is_some_and(|fields| fields.is_empty()) {
+                emptied.insert((*id, name.clone()));
+            }
+        }
+        for (name, saved) in scene.components.iter_mut() {
+            saved.retain(|(id, _)| !emptied.contains(&(*id, name.clone())));
+        }
+
+        for (id, link) in members.values() {
+            let link = Link { removed: removed.remove(id).unwrap_or_default(), ..link.clone() };
+            let index = index_of(scene, *id, INSTANCE);
+            scene.components.get_mut(INSTANCE).unwrap()[index].1 = serde_json::to_value(link).unwrap();
+        }
+    }
+}
+
+fn index_of(scene: &Scene, id: u64, name: &str) -> usize {
+    scene.components[name].iter().position(|(saved, _)| *saved == id).unwrap()
+}
+
+/// Fills prefab instances in from their prefabs: every component the prefab entity has,
+/// unless the instance removed it, with the instance's changed fields on top. Prefab entities
+/// the instance lacks are added, and instance entities no longer in the prefab are dropped.
+/// Instances of prefabs that can't be read keep what was saved for them, like `strip` leaves them.
+pub fn expand(scene: &mut Scene) {
+    expand_with(scene, &mut load);
+}
+
+fn expand_with(scene: &mut Scene, load: &mut dyn FnMut(&str) -> Result<Arc<Scene>, SceneError>) {
+    let mut next_id = scene.entities().last().map(|id| id + 1).unwrap_or(1 << 32);
+    for (root_id, (prefab_name, members)) in instances(scene) {
+        let prefab = match load(&prefab_name) {
+            Ok(prefab) => prefab,
+            Err(err) => {
+                println!("Loading instances of {} as saved: {}", prefab_name, err);
+                continue;
+            }
+        };
+        let sources = prefab.entities();
+
+        let mut ids: HashMap<u64, u64> = HashMap::new();
+        let mut removed: HashMap<u64, Vec<String>> = HashMap::new();
+        for source in &sources {
+            let id = match members.get(source) {
+                Some((id, link)) => {
+                    removed.insert(*id, link.removed.clone());
+                    *id
+                }
+                None => {
+                    next_id += 1;
+                    next_id - 1
+                }
+            };
+            ids.insert(*source, id);
+        }
+        let root = root(&prefab).and_then(|source| ids.get(&source).copied()).unwrap_or(root_id);
+        let values = match prefab_values(&prefab, &ids) {
+            Ok(values) => values,
+            Err(err) => {
+                println!("Loading instances of {} as saved: {}", prefab_name, err);
+                continue;
+            }
+        };
+
+        let gone: HashSet<u64> = members.iter().filter(|(source, _)| !sources.contains(source)).map(|(_, (id, _))| *id).collect();
+        for saved in scene.components.values_mut() {
+            saved.retain(|(id, _)| !gone.contains(id));
+        }
+
+        let index = index(scene);
+        for ((id, name), mut value) in values {
+            if removed.get(&id).is_some_and(|removed| removed.contains(&name)) {
+                continue;
+            }
+            match index.get(&(id, name.clone())) {
+                Some(&i) => {
+                    let saved = &mut scene.components.get_mut(&name).unwrap()[i].1;
+                    if let (Some(fields), Some(base)) = (saved.as_object(), value.as_object_mut()) {
+                        base.extend(fields.clone());
+                        *saved = value;
+                    }
+                }
+                None => scene.components.entry(name).or_default().push((id, value)),
+            }
+        }
+
+        for (source, id) in &ids {
+            if !members.contains_key(source) {
+                let link = Link { prefab: prefab_name.clone(), root: Some(root), source: *source, removed: Vec::new() };
+                scene.components.entry(INSTANCE.to_string()).or_default().push((*id, serde_json::to_value(link).unwrap()));
+            }
+        }
+    }
+    for saved in scene.components.values_mut() {
+        saved.sort_by_key(|(id, _)| *id);
+    }
+}
+
+/// Saves `entity` and its descendants as `assets/prefabs/<name>.json`, and makes them the
+/// first instance of it.
+pub fn save_prefab(world: &mut World, entity: Entity, name: &str) -> Result<String, SceneError> {
+    let file = format!("{}.json", name);
+    let subtree = hierarchy::descendants(world, entity);
+    let mut prefab = Scene::from_entities(world, &subtree);
+
+    // Nested instances are saved flat, and the root is placed by whoever instantiates it
+    prefab.components.remove(INSTANCE);
+    if let Some(saved) = prefab.components.get_mut("Parent") {
+        let root = entity.to_bits().get();
+        saved.retain(|(id, _)| *id != root);
+    }
+
+    std::fs::create_dir_all(PREFABS_DIR).map_err(SceneError::Io)?;
+    std::fs::write(Path::new(PREFABS_DIR).join(&file), prefab.encode(SceneFormat::Text)).map_err(SceneError::Io)?;
+    with_prefabs(|prefabs| prefabs.loaded.remove(&file));
+
+    for member in subtree {
+        let link = PrefabInstance { prefab: file.clone(), root: EntityRef::new(entity), source: member.to_bits().get(), removed: Vec::new() };
+        world.insert_one(member, link).unwrap();
+    }
+    Ok(file)
+}
+
+/// Adds a new instance of the prefab to the world and returns its root.
+pub fn instantiate(world: &mut World, name: &str) -> Result<Entity, SceneError> {
+    let prefab = load(name)?;
+    let source = root(&prefab).ok_or_else(|| SceneError::Prefab { prefab: name.to_string(), error: Box::new(SceneError::Malformed("the prefab is empty".to_string())) })?;
+
+    // Only the root's link; `expand` adds the rest of the prefab
+    let root_id = 1 << 32;
+    let link = Link { prefab: name.to_string(), root: Some(root_id), source, removed: Vec::new() };
+    let mut scene = Scene::default();
+    scene.components.insert(INSTANCE.to_string(), vec![(root_id, serde_json::to_value(link).unwrap())]);
+    expand(&mut scene);
+
+    let ids = scene.spawn_additive(world)?;
+    hierarchy::update(world);
+    Ok(ids[&root_id])
+}
+
+/// Rereads prefab files that changed on disk and updates their instances, keeping each
+/// instance's own changes. A prefab that fails to load keeps its previous version.
+pub fn reload_changed(world: &mut World, dt: f32) {
+    let changed: Vec<(String, Arc<Scene>)> = with_prefabs(|prefabs| {
+        prefabs.since_check += dt;
+        if prefabs.since_check < RELOAD_INTERVAL {
+            return Vec::new();
+        }
+        prefabs.since_check = 0.0;
+        prefabs.loaded.iter()
+            .filter(|(name, prefab)| modified(&Path::new(PREFABS_DIR).join(name)) != prefab.modified)
+            .map(|(name, prefab)| (name.clone(), prefab.scene.clone()))
+            .collect()
+    });
+
+    for (name, old) in changed {
+        let new = match read(&name) {
+            Ok(prefab) => {
+                let scene = prefab.scene.clone();
+                with_prefabs(|prefabs| prefabs.loaded.insert(name.clone(), prefab));
+                scene
+            }
+            Err(err) => {
+                println!("{}", err);
+                with_prefabs(|prefabs| prefabs.loaded.get_mut(&name).unwrap().modified = modified(&Path::new(PREFABS_DIR).join(&name)));
+                continue;
+            }
+        };
+
+        let members: Vec<Entity> = world.query::<&PrefabInstance>().iter()
+            .filter(|(_, link)| link.prefab == name)
+            .map(|(entity, _)| entity)
+            .collect();
+        if let Err(err) = update_instances(world, &members, &name, &old, &new) {
+            println!("Failed to update instances of {}: {}", name, err);
+        } else if !members.is_empty() {
+            println!("Reloaded prefab {}", name);
+        }
+    }
+}
+
+/// Moves instances from the `old` version of a prefab to the `new` one. Entities keep their
+/// ids, so references to them from elsewhere stay valid.
+fn update_instances(world: &mut World, members: &[Entity], name: &str, old: &Arc<Scene>, new: &Arc<Scene>) -> Result<(), SceneError> {
+    let mut scene = Scene::from_entities(world, members);
+    strip_with(&mut scene, &mut |prefab| if prefab == name { Ok(old.clone()) } else { load(prefab) });
+    expand_with(&mut scene, &mut |prefab| if prefab == name { Ok(new.clone()) } else { load(prefab) });
+
+    let existing: HashSet<u64> = members.iter().map(|entity| entity.to_bits().get()).collect();
+    let ids = scene.entities();
+    let mut entities: HashMap<u64, Entity> = HashMap::new();
+    for id in &ids {
+        let entity = if existing.contains(id) { Entity::from_bits(*id).unwrap() } else { world.spawn(()) };
+        entities.insert(*id, entity);
+    }
+
+    let mut cmd = CommandBuffer::new();
+    for member in members {
+        let id = member.to_bits().get();
+        if !ids.contains(&id) {
+            cmd.despawn(*member);
+            continue;
+        }
+        for info in reflect::components() {
+            let kept = scene.components.get(info.name).is_some_and(|saved| saved.iter().any(|(saved, _)| *saved == id));
+            if !kept && (info.has)(world, *member) {
+                (info.remove)(&mut cmd, *member);
+            }
+        }
+    }
+    cmd.run_on(world);
+
+    entity_ref::with_remap(&entities, true, || {
+        for (component, saved) in scene.components {
+            let info = reflect::component(&component).ok_or_else(|| SceneError::UnknownComponent(component.clone()))?;
+            for (id, value) in saved {
+                (info.load)(world, entities[&id], value)
+                    .map_err(|message| SceneError::Field { component: component.clone(), entity: id, message })?;
+            }
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,80 @@
+use std::collections::{HashMap, VecDeque};
+use crate::resources::Resource;
+
+/// Number of frames the rolling statistics are computed over.
+pub const PROFILER_WINDOW: usize = 120;
+
+#[derive(Default)]
+struct Timing {
+    /// Time spent so far in the current frame; fixed systems can run several times per frame.
+    current: f32,
+    history: VecDeque<f32>,
+}
+
+#[derive(Clone, Copy)]
+#[cfg_attr(not(debug_assertions), allow(dead_code))]
+pub struct TimingStats {
+    pub min: f32,
+    pub avg: f32,
+    pub max: f32,
+}
+
+/// Per-system frame timings in milliseconds, filled in by the system runner.
+#[derive(Default)]
+pub struct Profiler {
+    timings: HashMap<&'static str, Timing>,
+    frame_times: VecDeque<f32>,
+}
+
+impl Resource for Profiler {}
+
+fn push_sample(history: &mut VecDeque<f32>, sample: f32) {
+    if history.len() == PROFILER_WINDOW {
+        history.pop_front();
+    }
+    history.push_back(sample);
+}
+
+impl Profiler {
+    pub fn record(&mut self, name: &'static str, ms: f32) {
+        self.timings.entry(name).or_default().current += ms;
+    }
+
+    /// Closes the current frame. Systems that didn't run this frame get a zero sample.
+    pub fn end_frame(&mut self, frame_ms: f32) {
+        for timing in self.timings.values_mut() {
+            push_sample(&mut timing.history, std::mem::take(&mut timing.current));
+        }
+        push_sample(&mut self.frame_times, frame_ms);
+    }
+}
+
+/// Read side, used by the editor's Systems window.
+#[cfg_attr(not(debug_assertions), allow(dead_code))]
+impl Profiler {
+    pub fn stats(&self, name: &str) -> Option<TimingStats> {
+        let history = &self.timings.get(name)?.history;
+        if history.is_empty() {
+            return None;
+        }
+        let (min, max, sum) = history.iter().fold((f32::MAX, 0.0f32, 0.0), |(min, max, sum), &t| {
+            (min.min(t), max.max(t), sum + t)
+        });
+        Some(TimingStats { min, avg: sum / history.len() as f32, max })
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.timings.keys().copied()
+    }
+
+    pub fn frame_times(&self) -> &VecDeque<f32> {
+        &self.frame_times
+    }
+}
+
+/// Runs `f` and returns its result with the elapsed time in milliseconds.
+pub fn timed<R>(f: impl FnOnce() -> R) -> (R, f32) {
+    let start = macroquad::time::get_time();
+    let result = f();
+    (result, ((macroquad::time::get_time() - start) * 1000.0) as f32)
+}
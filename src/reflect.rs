@@ -0,0 +1,214 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use hecs::{Entity, World};
+
+/// Inspector attributes of a field: `#[range(a, b)]`, `#[slider]`, `#[step(x)]`,
+/// `#[tooltip("...")]`, `#[hidden]` and `#[readonly]`.
+#[derive(Clone, Copy)]
+pub struct FieldHints {
+    pub range: Option<(f64, f64)>,
+    pub slider: bool,
+    pub step: Option<f64>,
+    pub tooltip: Option<&'static str>,
+    pub hidden: bool,
+    pub readonly: bool,
+}
+
+impl FieldHints {
+    pub const NONE: Self = Self { range: None, slider: false, step: None, tooltip: None, hidden: false, readonly: false };
+}
+
+/// One field of a component, as declared in its `#[component]` struct.
+pub struct FieldInfo {
+    pub name: &'static str,
+    /// The type as written in the declaration, e.g. `[f32; 4]`.
+    pub type_name: &'static str,
+    pub type_id: fn() -> TypeId,
+    /// Byte offset of the field inside the component.
+    pub offset: usize,
+    /// The declared default (or `Default::default()`), boxed.
+    pub default: fn() -> Box<dyn Any>,
+    /// Accessors from the component (`&dyn Any` of the component type) to the field.
+    pub get: fn(&dyn Any) -> &dyn Any,
+    pub get_mut: fn(&mut dyn Any) -> &mut dyn Any,
+    /// Sets the field back to its declared default.
+    pub reset: fn(&mut dyn Any),
+    /// The field's inspector editor, if its type implements `Inspect`.
+    #[cfg(debug_assertions)]
+    pub inspect: fn() -> Option<crate::inspector::InspectFn>,
+    pub hints: FieldHints,
+}
+
+/// One component type of a scene: `(entity bits, value)` pairs.
+pub type SavedComponents = Vec<(u64, serde_json::Value)>;
+
+/// Called with the component as `&mut dyn Any`.
+pub type ComponentVisitor<'a> = &'a mut dyn FnMut(&mut dyn Any);
+
+/// What `#[component]` requires of a type; its output implements all of it.
+pub trait Component: hecs::Component + Clone + Default + serde::Serialize + serde::de::DeserializeOwned {}
+
+impl<T: hecs::Component + Clone + Default + serde::Serialize + serde::de::DeserializeOwned> Component for T {}
+
+/// Everything tools need to handle a component without knowing its type.
+pub struct ComponentInfo {
+    pub name: &'static str,
+    /// Version of the saved layout, raised by `#[component(version = N)]`; see `crate::migrate`.
+    pub version: u32,
+    pub type_id: fn() -> TypeId,
+    pub fields: &'static [FieldInfo],
+    pub has: fn(&World, Entity) -> bool,
+    pub insert_default: fn(&mut hecs::CommandBuffer, Entity),
+    pub remove: fn(&mut hecs::CommandBuffer, Entity),
+    /// The entity's component, if any, as saved in scenes.
+    pub save_one: fn(&World, Entity) -> Option<Result<serde_json::Value, String>>,
+    /// Calls `f` with the entity's component; returns `false` if it doesn't have one.
+    pub with_mut: fn(&World, Entity, ComponentVisitor) -> bool,
+    /// The component of every entity that has one, keyed by entity bits, as saved in scenes.
+    pub save: fn(&World) -> Result<SavedComponents, String>,
+    /// Inserts or replaces the component with a saved value.
+    pub load: fn(&mut World, Entity, serde_json::Value) -> Result<(), String>,
+    /// A saved value read back and saved again: missing fields filled in, entity references remapped.
+    pub normalize: fn(serde_json::Value) -> Result<serde_json::Value, String>,
+    pub to_script: fn(&World, Entity) -> Option<Result<rhai::Dynamic, String>>,
+    /// Inserts or replaces the component with a value coming back from a script.
+    pub from_script: fn(&mut World, Entity, &rhai::Dynamic) -> Result<(), String>,
+}
+
+impl ComponentInfo {
+    /// Hooks for `T`; `#[component]` only has to add the name and fields.
+    pub const fn of<T: Component>(name: &'static str, fields: &'static [FieldInfo]) -> Self {
+        Self {
+            name,
+            version: 0,
+            type_id: TypeId::of::<T>,
+            fields,
+            has: has::<T>,
+            insert_default: insert_default::<T>,
+            remove: remove::<T>,
+            save_one: save_one::<T>,
+            with_mut: with_mut::<T>,
+            save: save::<T>,
+            load: load::<T>,
+            normalize: normalize::<T>,
+            to_script: to_script::<T>,
+            from_script: from_script::<T>,
+        }
+    }
+
+    pub const fn with_version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn field(&self, name: &str) -> Option<&'static FieldInfo> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+}
+
+fn has<T: Component>(world: &World, entity: Entity) -> bool {
+    world.get::<&T>(entity).is_ok()
+}
+
+fn insert_default<T: Component>(cmd: &mut hecs::CommandBuffer, entity: Entity) {
+    cmd.insert_one(entity, T::default());
+}
+
+fn remove<T: Component>(cmd: &mut hecs::CommandBuffer, entity: Entity) {
+    cmd.remove_one::<T>(entity);
+}
+
+fn save_one<T: Component>(world: &World, entity: Entity) -> Option<Result<serde_json::Value, String>> {
+    let comp = world.get::<&T>(entity).ok()?;
+    Some(serde_json::to_value(&*comp).map_err(|err| err.to_string()))
+}
+
+fn with_mut<T: Component>(world: &World, entity: Entity, f: ComponentVisitor) -> bool {
+    match world.get::<&mut T>(entity) {
+        Ok(mut comp) => {
+            f(&mut *comp);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+fn save<T: Component>(world: &World) -> Result<SavedComponents, String> {
+    world.query::<&T>().iter()
+        .map(|(entity, comp)| Ok((entity.to_bits().get(), serde_json::to_value(comp).map_err(|err| err.to_string())?)))
+        .collect()
+}
+
+fn normalize<T: Component>(value: serde_json::Value) -> Result<serde_json::Value, String> {
+    let comp: T = serde_json::from_value(value).map_err(|err| err.to_string())?;
+    serde_json::to_value(comp).map_err(|err| err.to_string())
+}
+
+fn load<T: Component>(world: &mut World, entity: Entity, value: serde_json::Value) -> Result<(), String> {
+    let comp: T = serde_json::from_value(value).map_err(|err| err.to_string())?;
+    world.insert_one(entity, comp).map_err(|err| err.to_string())
+}
+
+fn to_script<T: Component>(world: &World, entity: Entity) -> Option<Result<rhai::Dynamic, String>> {
+    let comp = world.get::<&T>(entity).ok()?;
+    Some(rhai::serde::to_dynamic(&*comp).map_err(|err| err.to_string()))
+}
+
+fn from_script<T: Component>(world: &mut World, entity: Entity, value: &rhai::Dynamic) -> Result<(), String> {
+    // Through JSON, which converts between the script's f64/i64 and the field's number type
+    let name = std::any::type_name::<T>().rsplit("::").next().unwrap_or_default();
+    let value = serde_json::to_value(value).map_err(|err| format!("{}: {}", name, err))?;
+    load::<T>(world, entity, value).map_err(|err| format!("{}: {}", name, err))
+}
+
+inventory::collect!(ComponentInfo);
+
+/// Expanded by `#[component]` to register its `ComponentInfo`. The hot-reload library
+/// (`game_systems`) defines its own version that leaves the engine's registry alone.
+macro_rules! register_component {
+    ($info:expr) => {
+        inventory::submit! { $info }
+    };
+}
+pub(crate) use register_component;
+
+/// Every registered component, sorted by name so tools list them in a stable order.
+pub fn components() -> &'static [&'static ComponentInfo] {
+    static SORTED: std::sync::OnceLock<Vec<&'static ComponentInfo>> = std::sync::OnceLock::new();
+    SORTED.get_or_init(|| {
+        let mut sorted: Vec<_> = inventory::iter::<ComponentInfo>.into_iter().collect();
+        sorted.sort_by_key(|info| info.name);
+        sorted
+    })
+}
+
+/// Looks a registered component up by its type name.
+pub fn component(name: &str) -> Option<&'static ComponentInfo> {
+    components().iter().copied().find(|c| c.name == name)
+}
+
+/// Copies `entities` with all their components. `EntityRef`s between them point to the
+/// matching copies; other references keep their target. Returns the copies in the same order.
+pub fn duplicate_entities(world: &mut World, entities: &[Entity]) -> Vec<Entity> {
+    let ids: HashMap<u64, Entity> = entities.iter().map(|entity| (entity.to_bits().get(), world.spawn(()))).collect();
+    let copies: Vec<Entity> = entities.iter().map(|entity| ids[&entity.to_bits().get()]).collect();
+
+    crate::entity_ref::with_remap(&ids, true, || {
+        for (&entity, &copy) in entities.iter().zip(&copies) {
+            for info in components() {
+                let copied = match (info.save_one)(world, entity) {
+                    Some(value) => value.and_then(|value| (info.load)(world, copy, value)),
+                    None => Ok(()),
+                };
+                if let Err(err) = copied {
+                    println!("Failed to copy {}: {}", info.name, err);
+                }
+            }
+        }
+    });
+    copies
+}
+
+pub fn duplicate_entity(world: &mut World, entity: Entity) -> Entity {
+    duplicate_entities(world, &[entity])[0]
+}
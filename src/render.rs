@@ -2,11 +2,21 @@ use hecs::World;
 use macroquad::prelude::*;
 use crate::components::*;
 use crate::sprite_manager::SpriteManager;
+use crate::physics::PrevPos;
 
 pub const PPU: f32 = 128.0;
 
+/// Blends between the position of the previous and the current fixed step.
+pub fn lerp_pos(pos: &Pos, prev: Option<&PrevPos>, alpha: f32) -> Vec2 {
+    match prev {
+        Some(prev) => vec2(prev.x, prev.y).lerp(vec2(pos.x, pos.y), alpha),
+        None => vec2(pos.x, pos.y),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn render_world(
-    world: &mut World, sprites: &mut SpriteManager, camera: &Camera2D, zoom: f32, 
+    world: &mut World, sprites: &mut SpriteManager, camera: &Camera2D, zoom: f32, alpha: f32,
     #[cfg(debug_assertions)] show_editor: bool, 
     #[cfg(debug_assertions)] brush_mode: bool,
     #[cfg(debug_assertions)] selected: Option<hecs::Entity>
@@ -57,7 +67,7 @@ pub fn render_world(
         for y in start_y.max(0)..=end_y.min((tm.height - 1) as i32) {
             for x in start_x.max(0)..=end_x.min((tm.width - 1) as i32) {
                 let idx = (y as usize) * tm.width + (x as usize);
-                let sprite_idx = tm.tiles[idx];
+                let sprite_idx = tm.tiles.get(idx).copied().unwrap_or(0);
                 
                 if sprite_idx != 0 {
                     if let Some(sprite) = sprites.sprites.get(&sprite_idx) {
@@ -79,8 +89,10 @@ pub fn render_world(
         }
     }
 
-    for (_id, (pos, ren)) in world.query_mut::<(&Pos, &mut Render)>() {
-        let sprite_rect = Rect::new(pos.x - ren.w / 2.0, pos.y - ren.h / 2.0, ren.w, ren.h);
+    for (_id, (pos, ren, prev, transform)) in world.query_mut::<(&Pos, &mut Render, Option<&PrevPos>, Option<&Transform>)>() {
+        let pos = lerp_pos(pos, prev, alpha);
+        let transform = transform.cloned().unwrap_or_default();
+        let sprite_rect = transform.bounds(pos, Vec2::ZERO, vec2(ren.w, ren.h));
         
         if !cam_rect.overlaps(&sprite_rect) {
             continue; 
@@ -90,15 +102,20 @@ pub fn render_world(
         }
         
         if let Some(sprite) = &ren.cached_sprite {
+            // A negative scale mirrors the sprite on that axis
+            let size = vec2(ren.w * transform.scale[0], ren.h * transform.scale[1]);
+            let (mirror_x, mirror_y) = (size.x < 0.0, size.y < 0.0);
             draw_texture_ex(
                 &sprite.texture,
-                pos.x - ren.w / 2.0, pos.y - ren.h / 2.0,
+                pos.x - size.x.abs() / 2.0, pos.y - size.y.abs() / 2.0,
                 Color::new(ren.r(), ren.g(), ren.b(), ren.a()),
                 DrawTextureParams {
                     source: Some(sprite.source_rect),
-                    flip_x: ren.flip_x,
-                    flip_y: !ren.flip_y,
-                    dest_size: Some(vec2(ren.w, ren.h)),
+                    rotation: transform.radians(),
+                    flip_x: ren.flip_x != mirror_x,
+                    // Same as `!ren.flip_y != mirror_y`: textures need a flip to stand upright
+                    flip_y: ren.flip_y == mirror_y,
+                    dest_size: Some(size.abs()),
                     ..Default::default()
                 }
             );
@@ -107,17 +124,22 @@ pub fn render_world(
 
     #[cfg(debug_assertions)]
     if show_editor {
-        for (_id, (pos, col)) in world.query_mut::<(&Pos, &Collider)>() {
-            draw_rectangle_lines(pos.x - col.w / 2.0, pos.y - col.h / 2.0, col.w, col.h, 1.0, GREEN);
+        for (_id, (pos, col, transform)) in world.query_mut::<(&Pos, &Collider, Option<&Transform>)>() {
+            let rect = col.rect(pos, transform);
+            draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, GREEN);
         }
 
         if let Some(entity) = selected {
             if let Ok(pos) = world.get::<&Pos>(entity) {
+                let transform = world.get::<&Transform>(entity).ok();
                 if let Ok(ren) = world.get::<&Render>(entity) {
-                    draw_rectangle_lines(pos.x - ren.w / 2.0 - 2.0, pos.y - ren.h / 2.0 - 2.0, ren.w + 4.0, ren.h + 4.0, 2.0, WHITE);
+                    let corners = transform.as_deref().cloned().unwrap_or_default()
+                        .corners(vec2(pos.x, pos.y), Vec2::ZERO, vec2(ren.w + 4.0, ren.h + 4.0));
+                    draw_outline(&corners, 2.0, WHITE);
                 }
                 if let Ok(col) = world.get::<&Collider>(entity) {
-                    draw_rectangle_lines(pos.x - col.w / 2.0, pos.y - col.h / 2.0, col.w, col.h, 2.0, RED);
+                    let rect = col.rect(&pos, transform.as_deref());
+                    draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 2.0, RED);
                 }
             }
         }
@@ -146,4 +168,12 @@ pub fn render_world(
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(debug_assertions)]
+fn draw_outline(corners: &[Vec2; 4], thickness: f32, color: Color) {
+    for (i, from) in corners.iter().enumerate() {
+        let to = corners[(i + 1) % corners.len()];
+        draw_line(from.x, from.y, to.x, to.y, thickness, color);
+    }
+}
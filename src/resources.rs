@@ -0,0 +1,88 @@
+use std::any::{Any, TypeId, type_name};
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Marker for types that can be stored in `Resources` and requested by `#[system]` parameters.
+pub trait Resource: Any + Send + Sync {}
+
+type Slot = RwLock<Box<dyn Any + Send + Sync>>;
+
+/// Global state keyed by type: score, settings, RNG and the like.
+#[derive(Default)]
+pub struct Resources {
+    map: HashMap<TypeId, Slot>,
+}
+
+pub struct Res<'a, T: Resource> {
+    guard: RwLockReadGuard<'a, Box<dyn Any + Send + Sync>>,
+    _marker: std::marker::PhantomData<T>,
+}
+
+pub struct ResMut<'a, T: Resource> {
+    guard: RwLockWriteGuard<'a, Box<dyn Any + Send + Sync>>,
+    _marker: std::marker::PhantomData<T>,
+}
+
+impl Resources {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert<T: Resource>(&mut self, value: T) {
+        self.map.insert(TypeId::of::<T>(), RwLock::new(Box::new(value)));
+    }
+
+    pub fn remove<T: Resource>(&mut self) -> Option<T> {
+        let slot = self.map.remove(&TypeId::of::<T>())?;
+        let boxed = slot.into_inner().unwrap_or_else(|e| e.into_inner());
+        boxed.downcast::<T>().ok().map(|b| *b)
+    }
+
+    pub fn contains<T: Resource>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Panics if the resource is already borrowed mutably.
+    pub fn get<T: Resource>(&self) -> Option<Res<'_, T>> {
+        let slot = self.map.get(&TypeId::of::<T>())?;
+        let guard = slot.try_read().unwrap_or_else(|_| panic!("resource `{}` is already borrowed mutably", type_name::<T>()));
+        Some(Res { guard, _marker: std::marker::PhantomData })
+    }
+
+    /// Panics if the resource is already borrowed.
+    pub fn get_mut<T: Resource>(&self) -> Option<ResMut<'_, T>> {
+        let slot = self.map.get(&TypeId::of::<T>())?;
+        let guard = slot.try_write().unwrap_or_else(|_| panic!("resource `{}` is already borrowed", type_name::<T>()));
+        Some(ResMut { guard, _marker: std::marker::PhantomData })
+    }
+
+    /// Used by `#[system]` wrappers, where a missing resource is a setup bug.
+    pub fn expect<T: Resource>(&self) -> Res<'_, T> {
+        self.get().unwrap_or_else(|| panic!("resource `{}` was requested by a system but never inserted", type_name::<T>()))
+    }
+
+    pub fn expect_mut<T: Resource>(&self) -> ResMut<'_, T> {
+        self.get_mut().unwrap_or_else(|| panic!("resource `{}` was requested by a system but never inserted", type_name::<T>()))
+    }
+}
+
+impl<T: Resource> Deref for Res<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.guard.downcast_ref().unwrap()
+    }
+}
+
+impl<T: Resource> Deref for ResMut<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.guard.downcast_ref().unwrap()
+    }
+}
+
+impl<T: Resource> DerefMut for ResMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.guard.downcast_mut().unwrap()
+    }
+}
@@ -0,0 +1,467 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+use crate::reflect::{self, SavedComponents};
+
+/// Layout of scene files. Scenes from before it was recorded are read as version 0.
+pub const SCENE_VERSION: u32 = 1;
+
+/// Binary scene, loaded by shipped builds.
+pub const SCENE_BIN: &str = "Scene.bin";
+/// Text version of the same scene, for reviewing and merging level edits.
+pub const SCENE_TEXT: &str = "Scene.json";
+
+/// The single scene of projects from before `assets/scenes`: the text one when the project has it.
+/// Still loaded as the start scene when `assets/scenes` has none (see `scene_manager`).
+pub fn scene_path() -> &'static str {
+    if Path::new(SCENE_TEXT).exists() { SCENE_TEXT } else { SCENE_BIN }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SceneFormat {
+    /// MessagePack, compact and fast to load.
+    Binary,
+    /// Pretty-printed JSON with sorted keys, grouped by entity so a diff shows which entity changed.
+    Text,
+}
+
+impl SceneFormat {
+    /// `.json` files are text, anything else binary.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some("json") => SceneFormat::Text,
+            _ => SceneFormat::Binary,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    /// The data ends in the middle of the scene, e.g. a file that was only partly written.
+    Truncated,
+    /// The data isn't a scene in the expected format.
+    Malformed(String),
+    /// The scene has a component this build doesn't register.
+    UnknownComponent(String),
+    /// A saved component doesn't match its type, e.g. a field holding the wrong kind of value.
+    Field { component: String, entity: u64, message: String },
+    /// The scene, or one of its components, was saved by a newer build.
+    Newer { what: String, saved: u32, supported: u32 },
+    /// A prefab used by the scene couldn't be read.
+    Prefab { prefab: String, error: Box<SceneError> },
+    Io(std::io::Error),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Truncated => write!(f, "the scene data ends unexpectedly"),
+            SceneError::Malformed(message) => write!(f, "not a valid scene: {}", message),
+            SceneError::UnknownComponent(name) => write!(f, "unknown component `{}`", name),
+            SceneError::Field { component, entity, message } => {
+                write!(f, "component `{}` of entity {}: {}", component, entity, message)
+            }
+            SceneError::Newer { what, saved, supported } => {
+                write!(f, "`{}` was saved as version {}, but this build only reads up to version {}", what, saved, supported)
+            }
+            SceneError::Prefab { prefab, error } => write!(f, "prefab `{}`: {}", prefab, error),
+            SceneError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+/// A saved world: every registered component, keyed by type name. Entity bits only tie
+/// the components of one entity together; they are remapped on load.
+#[derive(Default, Clone)]
+pub struct Scene {
+    /// Saved version of each component not at version 0; see `crate::migrate`.
+    pub versions: BTreeMap<String, u32>,
+    pub components: BTreeMap<String, SavedComponents>,
+}
+
+/// Components grouped by entity instead of by type, as in the text format.
+type EntityMajor = BTreeMap<u64, BTreeMap<String, serde_json::Value>>;
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct BinaryLayout {
+    version: u32,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    versions: BTreeMap<String, u32>,
+    components: BTreeMap<String, SavedComponents>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct TextLayout {
+    version: u32,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    versions: BTreeMap<String, u32>,
+    entities: EntityMajor,
+}
+
+impl Scene {
+    pub fn from_world(world: &hecs::World) -> Self {
+        let mut scene = Scene { versions: crate::migrate::current_versions(), ..Default::default() };
+        for info in reflect::components() {
+            match (info.save)(world) {
+                Ok(mut saved) => {
+                    saved.sort_by_key(|(id, _)| *id);
+                    scene.components.insert(info.name.to_string(), saved);
+                }
+                Err(err) => println!("Failed to save {}: {}", info.name, err),
+            }
+        }
+        scene
+    }
+
+    /// Like `from_world`, but only with the given entities.
+    pub fn from_entities(world: &hecs::World, entities: &[hecs::Entity]) -> Self {
+        let mut scene = Scene { versions: crate::migrate::current_versions(), ..Default::default() };
+        for info in reflect::components() {
+            let mut saved: SavedComponents = Vec::new();
+            for entity in entities {
+                match (info.save_one)(world, *entity) {
+                    Some(Ok(value)) => saved.push((entity.to_bits().get(), value)),
+                    Some(Err(err)) => println!("Failed to save {}: {}", info.name, err),
+                    None => {}
+                }
+            }
+            if !saved.is_empty() {
+                saved.sort_by_key(|(id, _)| *id);
+                scene.components.insert(info.name.to_string(), saved);
+            }
+        }
+        scene
+    }
+
+    /// Every entity id used in the scene, sorted.
+    pub fn entities(&self) -> Vec<u64> {
+        let mut ids: Vec<u64> = self.components.values().flatten().map(|(id, _)| *id).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    pub fn encode(&self, format: SceneFormat) -> Vec<u8> {
+        match format {
+            SceneFormat::Binary => {
+                let layout = BinaryLayout { version: SCENE_VERSION, versions: self.versions.clone(), components: self.components.clone() };
+                rmp_serde::to_vec_named(&layout).expect("Failed to serialize scene")
+            }
+            SceneFormat::Text => {
+                let mut entities = EntityMajor::new();
+                for (name, saved) in &self.components {
+                    for (id, value) in saved {
+                        entities.entry(*id).or_default().insert(name.clone(), value.clone());
+                    }
+                }
+                let layout = TextLayout { version: SCENE_VERSION, versions: self.versions.clone(), entities };
+                let mut text = String::new();
+                write_text(&mut text, &serde_json::to_value(layout).expect("Failed to serialize scene"), 0);
+                text.push('\n');
+                text.into_bytes()
+            }
+        }
+    }
+
+    /// Reads a scene as it was saved; `crate::migrate::upgrade` brings it to the current layout.
+    pub fn decode(data: &[u8], format: SceneFormat) -> Result<Self, SceneError> {
+        let value: serde_json::Value = match format {
+            SceneFormat::Binary => rmp_serde::from_slice(data).map_err(|err| {
+                use rmp_serde::decode::Error;
+                match &err {
+                    Error::InvalidMarkerRead(io) | Error::InvalidDataRead(io) if io.kind() == std::io::ErrorKind::UnexpectedEof => SceneError::Truncated,
+                    _ => SceneError::Malformed(err.to_string()),
+                }
+            })?,
+            SceneFormat::Text => serde_json::from_slice(data).map_err(|err| match err.classify() {
+                serde_json::error::Category::Eof => SceneError::Truncated,
+                _ => SceneError::Malformed(err.to_string()),
+            })?,
+        };
+        let malformed = |err: serde_json::Error| SceneError::Malformed(err.to_string());
+
+        // Version 0 files are the bare component or entity map, without the `version` key
+        let version = value.get("version").and_then(|version| version.as_u64()).unwrap_or(0) as u32;
+        if version > SCENE_VERSION {
+            return Err(SceneError::Newer { what: "scene".to_string(), saved: version, supported: SCENE_VERSION });
+        }
+        let (versions, components) = match (format, version) {
+            (SceneFormat::Binary, 0) => (BTreeMap::new(), serde_json::from_value(value).map_err(malformed)?),
+            (SceneFormat::Binary, _) => {
+                let layout: BinaryLayout = serde_json::from_value(value).map_err(malformed)?;
+                (layout.versions, layout.components)
+            }
+            (SceneFormat::Text, _) => {
+                let (versions, entities) = if version == 0 {
+                    (BTreeMap::new(), serde_json::from_value::<EntityMajor>(value).map_err(malformed)?)
+                } else {
+                    let layout: TextLayout = serde_json::from_value(value).map_err(malformed)?;
+                    (layout.versions, layout.entities)
+                };
+                let mut components: BTreeMap<String, SavedComponents> = BTreeMap::new();
+                for (id, saved) in entities {
+                    for (name, value) in saved {
+                        components.entry(name).or_default().push((id, value));
+                    }
+                }
+                (versions, components)
+            }
+        };
+        Ok(Scene { versions, components })
+    }
+
+    /// Replaces everything in `world` with the scene's entities, and returns which entity each
+    /// saved id became. On error `world` is left as it was.
+    pub fn spawn_into(self, world: &mut hecs::World) -> Result<HashMap<u64, hecs::Entity>, SceneError> {
+        // Built on the side and swapped in at the end, so a bad component can't leave half a scene.
+        // Entities keep the index they were saved with, so saving again reproduces the same ids.
+        let mut staged = hecs::World::new();
+        let id_map = self.load_entities(&mut staged, |staged, old_id| {
+            let entity = hecs::Entity::from_bits((1 << 32) | (old_id & u32::MAX as u64)).unwrap();
+            if staged.contains(entity) {
+                staged.spawn(())
+            } else {
+                staged.spawn_at(entity, ());
+                entity
+            }
+        })?;
+        *world = staged;
+        Ok(id_map)
+    }
+
+    /// Adds the scene's entities to `world` next to the ones already there, and returns
+    /// which entity each saved id became. On error nothing is added.
+    pub fn spawn_additive(self, world: &mut hecs::World) -> Result<HashMap<u64, hecs::Entity>, SceneError> {
+        self.load_entities(world, |world, _| world.spawn(()))
+    }
+
+    /// Gives entities the ids in `ids` (old id to new), `EntityRef`s included. References to
+    /// entities missing from `ids` are cleared, since they point outside the scene.
+    pub fn renumber(&mut self, ids: &HashMap<u64, u64>) {
+        let remap: HashMap<u64, hecs::Entity> = ids.iter().filter_map(|(old_id, id)| Some((*old_id, hecs::Entity::from_bits(*id)?))).collect();
+        crate::entity_ref::with_remap(&remap, false, || {
+            for (name, saved) in &mut self.components {
+                let info = reflect::component(name);
+                for (id, value) in saved.iter_mut() {
+                    if let Some(info) = info {
+                        match (info.normalize)(value.clone()) {
+                            Ok(normalized) => *value = normalized,
+                            Err(err) => println!("Failed to renumber {} of {}: {}", name, id, err),
+                        }
+                    }
+                    if let Some(new_id) = ids.get(id) {
+                        *id = *new_id;
+                    }
+                }
+                saved.sort_by_key(|(id, _)| *id);
+            }
+        });
+    }
+
+    fn load_entities(
+        self,
+        world: &mut hecs::World,
+        mut spawn: impl FnMut(&mut hecs::World, u64) -> hecs::Entity,
+    ) -> Result<HashMap<u64, hecs::Entity>, SceneError> {
+        if let Some(name) = self.components.keys().find(|name| reflect::component(name).is_none()) {
+            return Err(SceneError::UnknownComponent(name.clone()));
+        }
+
+        // Every entity exists before any component is loaded, so `EntityRef`s can point forward
+        let mut id_map = HashMap::new();
+        for old_id in self.entities() {
+            id_map.insert(old_id, spawn(world, old_id));
+        }
+
+        let loaded = crate::entity_ref::with_remap(&id_map, false, || {
+            for (name, saved) in self.components {
+                let info = reflect::component(&name).unwrap();
+                for (old_id, value) in saved {
+                    (info.load)(world, id_map[&old_id], value)
+                        .map_err(|message| SceneError::Field { component: name.clone(), entity: old_id, message })?;
+                }
+            }
+            Ok(())
+        });
+        if let Err(err) = loaded {
+            for entity in id_map.values() {
+                let _ = world.despawn(*entity);
+            }
+            return Err(err);
+        }
+        Ok(id_map)
+    }
+}
+
+/// Saves the world. Prefab instances keep only the fields that differ from their prefab.
+pub fn save_scene(world: &hecs::World, format: SceneFormat) -> Vec<u8> {
+    let mut scene = Scene::from_world(world);
+    crate::prefab::strip(&mut scene);
+    scene.encode(format)
+}
+
+/// Like `save_scene`, but only with the given entities, saved under the ids in `ids`
+/// (entity bits to saved id).
+pub fn save_entities(world: &hecs::World, entities: &[hecs::Entity], ids: &HashMap<u64, u64>, format: SceneFormat) -> Vec<u8> {
+    let mut scene = Scene::from_entities(world, entities);
+    scene.renumber(ids);
+    crate::prefab::strip(&mut scene);
+    scene.encode(format)
+}
+
+/// Reads the scene in `data` ready to spawn: migrated to the current component versions and
+/// with prefab instances filled in.
+pub fn read_scene(data: &[u8], format: SceneFormat) -> Result<Scene, SceneError> {
+    let mut scene = Scene::decode(data, format)?;
+    crate::migrate::upgrade(&mut scene)?;
+    crate::prefab::expand(&mut scene);
+    Ok(scene)
+}
+
+/// Replaces the world with the scene in `data`. Fails without touching the world.
+pub fn load_scene(world: &mut hecs::World, data: &[u8], format: SceneFormat) -> Result<(), SceneError> {
+    read_scene(data, format)?.spawn_into(world).map(|_| ())
+}
+
+/// Rewrites a scene file in the format given by the extension of `to`. Component data is
+/// copied as saved, without migrating it.
+pub fn convert_scene_file(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<(), String> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+    let data = std::fs::read(from).map_err(|err| format!("{}: {}", from.display(), err))?;
+    let scene = Scene::decode(&data, SceneFormat::from_path(from)).map_err(|err| format!("{}: {}", from.display(), err))?;
+    std::fs::write(to, scene.encode(SceneFormat::from_path(to))).map_err(|err| format!("{}: {}", to.display(), err))
+}
+
+/// Writes the scene a shipped build loads: binary, migrated, and with prefab instances
+/// filled in and unlinked, so the game doesn't need the prefab files to load it.
+pub fn export_scene_file(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<(), String> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+    let data = std::fs::read(from).map_err(|err| format!("{}: {}", from.display(), err))?;
+    let mut scene = read_scene(&data, SceneFormat::from_path(from)).map_err(|err| format!("{}: {}", from.display(), err))?;
+    scene.components.remove(crate::prefab::INSTANCE);
+    std::fs::write(to, scene.encode(SceneFormat::Binary)).map_err(|err| format!("{}: {}", to.display(), err))
+}
+
+/// Arrays of plain values longer than this are wrapped, so editing one tile changes one line.
+const TEXT_ROW: usize = 16;
+
+/// Pretty-prints like `serde_json::to_string_pretty`, but with arrays of plain values kept on
+/// few lines and floats written as the shortest `f32` that reads back the same.
+fn write_text(out: &mut String, value: &serde_json::Value, indent: usize) {
+    use serde_json::Value;
+    let pad = |out: &mut String, level: usize| out.extend(std::iter::repeat_n("  ", level));
+
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            out.push_str("{\n");
+            for (i, (key, value)) in map.iter().enumerate() {
+                pad(out, indent + 1);
+                out.push_str(&serde_json::to_string(key).unwrap());
+                out.push_str(": ");
+                write_text(out, value, indent + 1);
+                out.push_str(if i + 1 < map.len() { ",\n" } else { "\n" });
+            }
+            pad(out, indent);
+            out.push('}');
+        }
+        Value::Array(items) if !items.is_empty() => {
+            let plain = items.iter().all(|item| !item.is_array() && !item.is_object());
+            if plain && items.len() <= TEXT_ROW {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 { out.push_str(", "); }
+                    write_text(out, item, indent);
+                }
+                out.push(']');
+                return;
+            }
+
+            out.push_str("[\n");
+            let rows: Vec<&[Value]> = if plain { items.chunks(TEXT_ROW).collect() } else { items.chunks(1).collect() };
+            for (i, row) in rows.iter().enumerate() {
+                pad(out, indent + 1);
+                for (j, item) in row.iter().enumerate() {
+                    if j > 0 { out.push_str(", "); }
+                    write_text(out, item, indent + 1);
+                }
+                out.push_str(if i + 1 < rows.len() { ",\n" } else { "\n" });
+            }
+            pad(out, indent);
+            out.push(']');
+        }
+        Value::Number(number) if number.is_f64() => {
+            let float = number.as_f64().unwrap();
+            if (float as f32) as f64 == float {
+                out.push_str(&format!("{:?}", float as f32));
+            } else {
+                out.push_str(&number.to_string());
+            }
+        }
+        _ => out.push_str(&value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::{Collider, Player, Pos};
+    use crate::entity_ref::EntityRef;
+    use crate::hierarchy::Parent;
+
+    fn world() -> hecs::World {
+        let mut world = hecs::World::new();
+        let player = world.spawn((
+            Pos { x: 0.1, y: -2.25 },
+            Collider { size: [10.0, 20.0], offset: [1.0, 2.0], is_static: false },
+            Player { speed: 7.0 },
+        ));
+        world.spawn((Pos { x: 3.0, y: 4.0 }, Parent { entity: EntityRef::new(player), x: 3.0, ..Default::default() }));
+        world
+    }
+
+    fn assert_same_world(loaded: &hecs::World) {
+        let mut players = loaded.query::<(&Pos, &Collider, &Player)>();
+        let [(player, (pos, collider, stats))] = players.iter().collect::<Vec<_>>()[..] else {
+            panic!("expected one player");
+        };
+        assert_eq!((pos.x, pos.y), (0.1, -2.25));
+        assert_eq!((collider.size, collider.offset, collider.is_static), ([10.0, 20.0], [1.0, 2.0], false));
+        assert_eq!(stats.speed, 7.0);
+
+        let mut children = loaded.query::<&Parent>();
+        let [(_, parent)] = children.iter().collect::<Vec<_>>()[..] else {
+            panic!("expected one child");
+        };
+        assert_eq!(parent.entity.get(loaded), Some(player));
+        assert_eq!(parent.x, 3.0);
+    }
+
+    #[test]
+    fn scenes_round_trip_in_both_formats() {
+        for format in [SceneFormat::Binary, SceneFormat::Text] {
+            let data = save_scene(&world(), format);
+            let mut loaded = hecs::World::new();
+            load_scene(&mut loaded, &data, format).unwrap();
+            assert_same_world(&loaded);
+        }
+    }
+
+    #[test]
+    fn converting_between_formats_keeps_the_scene() {
+        let text = save_scene(&world(), SceneFormat::Text);
+        let binary = Scene::decode(&text, SceneFormat::Text).unwrap().encode(SceneFormat::Binary);
+        let back = Scene::decode(&binary, SceneFormat::Binary).unwrap().encode(SceneFormat::Text);
+        assert_eq!(String::from_utf8(back).unwrap(), String::from_utf8(text).unwrap());
+    }
+
+    #[test]
+    fn truncated_scenes_are_reported() {
+        for format in [SceneFormat::Binary, SceneFormat::Text] {
+            let data = save_scene(&world(), format);
+            let result = Scene::decode(&data[..data.len() / 2], format);
+            assert!(matches!(result, Err(SceneError::Truncated)), "{:?}", format);
+        }
+    }
+}
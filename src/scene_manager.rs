@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use hecs::{CommandBuffer, Entity, World};
+use crate::editor::EditorAction;
+use crate::hierarchy;
+use crate::resources::Resource;
+use crate::scene::{self, SceneError, SceneFormat};
+use crate::systems::{Schedule, Stage, SysCtx, run_all_systems};
+
+/// Where named scenes live. A scene is named by its file name without the extension.
+pub const SCENES_DIR: &str = "assets/scenes";
+/// The scene the game starts with.
+pub const START_SCENE: &str = "main";
+
+/// The loaded scene an entity came from and its id in that scene's file. Entities without one
+/// (spawned by gameplay or in the editor) belong to the active scene. Not saved; it is set
+/// again whenever the scene loads.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SceneMember {
+    pub scene: String,
+    pub id: u64,
+}
+
+/// A scene in the world and the file it was loaded from, which the editor saves it back to.
+#[derive(Clone, Debug)]
+pub struct LoadedScene {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+enum SceneRequest {
+    Switch(String),
+    Reload,
+    LoadAdditive(String),
+    Unload(String),
+}
+
+/// The scenes in the world: the active one, which `switch` replaces, and the ones loaded on top
+/// of it with `load_additive` (a shared HUD, a level chunk). Requests are queued and applied
+/// between frames by `apply_requests`, in the order they were made.
+#[derive(Default)]
+pub struct SceneManager {
+    active: Option<LoadedScene>,
+    additive: Vec<LoadedScene>,
+    queued: Vec<SceneRequest>,
+    error: Option<String>,
+    generation: u64,
+}
+
+impl Resource for SceneManager {}
+
+impl SceneManager {
+    pub fn active(&self) -> Option<&LoadedScene> {
+        self.active.as_ref()
+    }
+
+    /// Scenes loaded with `load_additive`, in the order they were loaded.
+    pub fn additive(&self) -> &[LoadedScene] {
+        &self.additive
+    }
+
+    /// Every loaded scene, the active one first.
+    pub fn loaded(&self) -> impl Iterator<Item = &LoadedScene> {
+        self.active.iter().chain(&self.additive)
+    }
+
+    pub fn is_loaded(&self, name: &str) -> bool {
+        let name = scene_name(name);
+        self.loaded().any(|scene| scene.name == name)
+    }
+
+    /// Queues replacing the world with scene `name`. `Startup` and `OnSceneLoaded` systems run
+    /// again once it is loaded, and additive scenes are gone with the rest of the world.
+    pub fn switch(&mut self, name: &str) {
+        self.queued.push(SceneRequest::Switch(scene_name(name).to_string()));
+    }
+
+    /// Queues loading the scenes in the world again from their files, additive ones included.
+    /// Only `OnSceneLoaded` systems run afterwards, as the game was already started.
+    pub fn reload(&mut self) {
+        self.queued.push(SceneRequest::Reload);
+    }
+
+    /// Queues adding the entities of scene `name` to the world, after which `OnSceneLoaded`
+    /// systems run. Does nothing if the scene is already loaded.
+    pub fn load_additive(&mut self, name: &str) {
+        self.queued.push(SceneRequest::LoadAdditive(scene_name(name).to_string()));
+    }
+
+    /// Queues despawning the entities of an additive scene, with their children.
+    pub fn unload(&mut self, name: &str) {
+        self.queued.push(SceneRequest::Unload(scene_name(name).to_string()));
+    }
+
+    /// Why the last load failed, once.
+    pub fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+
+    /// Changes every time the world is replaced by a switch or reload, so entity handles kept
+    /// from before (which may now alias entities of the new scene) can be dropped.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+fn scene_name(name: &str) -> &str {
+    name.strip_suffix(".json").or_else(|| name.strip_suffix(".bin")).unwrap_or(name)
+}
+
+/// Files scene `name` can be in, in the order they are tried: the text one, then the binary one
+/// (the only one shipped builds have). The start scene falls back to the scene of projects
+/// from before `assets/scenes`.
+fn scene_files(name: &str) -> Vec<PathBuf> {
+    let dir = Path::new(SCENES_DIR);
+    let mut files = vec![dir.join(format!("{}.json", name)), dir.join(format!("{}.bin", name))];
+    if name == START_SCENE {
+        files.extend([PathBuf::from(scene::SCENE_TEXT), PathBuf::from(scene::SCENE_BIN)]);
+    }
+    files
+}
+
+/// Scenes in `assets/scenes`, sorted.
+pub fn list() -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(SCENES_DIR)
+        .map(|entries| entries.filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .filter(|name| name.ends_with(".json") || name.ends_with(".bin"))
+            .map(|name| scene_name(&name).to_string())
+            .collect())
+        .unwrap_or_default();
+    names.sort();
+    names.dedup();
+    names
+}
+
+async fn read(name: &str) -> Result<(PathBuf, scene::Scene), SceneError> {
+    for path in scene_files(name) {
+        if let Ok(data) = macroquad::file::load_file(&path.to_string_lossy()).await {
+            let scene = scene::read_scene(&data, SceneFormat::from_path(&path))?;
+            return Ok((path, scene));
+        }
+    }
+    let message = format!("no scene {} in {}", name, SCENES_DIR);
+    Err(SceneError::Io(std::io::Error::new(std::io::ErrorKind::NotFound, message)))
+}
+
+fn tag(world: &mut World, name: &str, ids: &HashMap<u64, Entity>) {
+    for (id, entity) in ids {
+        let _ = world.insert_one(*entity, SceneMember { scene: name.to_string(), id: *id });
+    }
+}
+
+/// Replaces the world with scene `name` and makes it the active scene, without running any
+/// systems. Fails without touching the world.
+pub async fn switch_now(world: &mut World, scenes: &mut SceneManager, name: &str) -> Result<(), SceneError> {
+    let name = scene_name(name);
+    let (path, scene) = read(name).await?;
+    let ids = scene.spawn_into(world)?;
+    tag(world, name, &ids);
+    scenes.active = Some(LoadedScene { name: name.to_string(), path });
+    scenes.additive.clear();
+    scenes.generation += 1;
+    Ok(())
+}
+
+/// Records a failed load for `SceneManager::take_error`, which the editor shows in a dialog.
+fn report(scenes: &mut SceneManager, name: &str, err: &SceneError) {
+    println!("cargo:warning=Failed to load scene {}: {}", name, err);
+    scenes.error = Some(format!("{}: {}", name, err));
+}
+
+/// Loads `START_SCENE` into the empty world at startup, without running any systems.
+/// A failure is reported like any other failed load; returns whether the scene loaded.
+pub async fn load_start(world: &mut World, scenes: &mut SceneManager) -> bool {
+    match switch_now(world, scenes, START_SCENE).await {
+        Ok(()) => true,
+        Err(err) => {
+            report(scenes, START_SCENE, &err);
+            false
+        }
+    }
+}
+
+/// Loads the active scene and then the additive ones again, without running any systems.
+/// Fails without touching the world if the active scene can't be loaded; additive scenes that
+/// fail are left out and reported.
+pub async fn reload_now(world: &mut World, scenes: &mut SceneManager) -> Result<(), SceneError> {
+    let active = scenes.active.as_ref().map_or(START_SCENE.to_string(), |scene| scene.name.clone());
+    let additive: Vec<String> = scenes.additive.iter().map(|scene| scene.name.clone()).collect();
+    switch_now(world, scenes, &active).await?;
+    for name in additive {
+        if let Err(err) = load_additive_now(world, scenes, &name).await {
+            report(scenes, &name, &err);
+        }
+    }
+    Ok(())
+}
+
+/// Adds the entities of scene `name` to the world, without running any systems.
+/// Fails without touching the world.
+pub async fn load_additive_now(world: &mut World, scenes: &mut SceneManager, name: &str) -> Result<(), SceneError> {
+    let name = scene_name(name);
+    let (path, scene) = read(name).await?;
+    let ids = scene.spawn_additive(world)?;
+    tag(world, name, &ids);
+    scenes.additive.push(LoadedScene { name: name.to_string(), path });
+    Ok(())
+}
+
+/// Despawns the entities of an additive scene. The active scene stays; switch to another one instead.
+pub fn unload_now(world: &mut World, scenes: &mut SceneManager, name: &str) {
+    let name = scene_name(name);
+    let Some(index) = scenes.additive.iter().position(|scene| scene.name == name) else {
+        println!("Warning: can't unload scene {}, it isn't loaded additively", name);
+        return;
+    };
+    scenes.additive.remove(index);
+
+    let mut cmd = CommandBuffer::new();
+    for (entity, member) in world.query::<&SceneMember>().iter() {
+        if member.scene == name {
+            hierarchy::despawn_recursive(&mut cmd, world, entity);
+        }
+    }
+    cmd.run_on(world);
+}
+
+/// Applies the queued requests and sends `EditorAction::SceneLoaded` after every successful load.
+/// Loads that fail leave the world as it was and are reported by `SceneManager::take_error`.
+/// Requests made by the systems run here wait for the next call.
+pub async fn apply_requests(schedule: &Schedule, ctx: &mut SysCtx<'_>) {
+    let requests = match ctx.resources.get_mut::<SceneManager>() {
+        Some(mut scenes) => std::mem::take(&mut scenes.queued),
+        None => return,
+    };
+
+    for request in requests {
+        let stages: &[Stage] = {
+            let mut scenes = ctx.resources.expect_mut::<SceneManager>();
+            let (name, loaded) = match &request {
+                SceneRequest::Switch(name) => {
+                    (name.clone(), switch_now(ctx.world, &mut scenes, name).await.map(|()| &[Stage::Startup, Stage::OnSceneLoaded][..]))
+                }
+                SceneRequest::Reload => {
+                    let name = scenes.active().map_or(START_SCENE.to_string(), |scene| scene.name.clone());
+                    (name, reload_now(ctx.world, &mut scenes).await.map(|()| &[Stage::OnSceneLoaded][..]))
+                }
+                SceneRequest::LoadAdditive(name) if scenes.is_loaded(name) => (name.clone(), Ok(&[][..])),
+                SceneRequest::LoadAdditive(name) => {
+                    (name.clone(), load_additive_now(ctx.world, &mut scenes, name).await.map(|()| &[Stage::OnSceneLoaded][..]))
+                }
+                SceneRequest::Unload(name) => {
+                    unload_now(ctx.world, &mut scenes, name);
+                    (name.clone(), Ok(&[][..]))
+                }
+            };
+            match loaded {
+                Ok(stages) => {
+                    if !stages.is_empty() {
+                        ctx.events.send(EditorAction::SceneLoaded);
+                    }
+                    stages
+                }
+                Err(err) => {
+                    report(&mut scenes, &name, &err);
+                    &[]
+                }
+            }
+        };
+        for stage in stages {
+            run_all_systems(schedule, *stage, ctx);
+        }
+    }
+}
+
+/// Writes every loaded scene back to its file, each with its own entities. Entities without a
+/// `SceneMember` go to the active scene (`main.json` when there are no scenes yet) and are tagged
+/// so saving again gives them the same ids. References between scenes are not saved.
+/// Refuses while the start scene exists but failed to load, so it isn't overwritten or shadowed.
+pub fn save_loaded(world: &mut World, scenes: &mut SceneManager) -> Result<(), String> {
+    if scenes.active.is_none()
+        && let Some(path) = scene_files(START_SCENE).into_iter().find(|path| path.exists())
+    {
+        return Err(format!("not saving, {} exists but isn't loaded; fix it and load it first", path.display()));
+    }
+
+    let active = scenes.active.get_or_insert_with(|| LoadedScene {
+        name: START_SCENE.to_string(),
+        path: Path::new(SCENES_DIR).join(format!("{}.json", START_SCENE)),
+    }).name.clone();
+
+    let mut members: HashMap<String, Vec<(Entity, Option<u64>)>> = HashMap::new();
+    for entity_ref in world.iter() {
+        let member = entity_ref.get::<&SceneMember>().filter(|member| scenes.is_loaded(&member.scene));
+        match member {
+            Some(member) => members.entry(member.scene.clone()).or_default().push((entity_ref.entity(), Some(member.id))),
+            None => members.entry(active.clone()).or_default().push((entity_ref.entity(), None)),
+        }
+    }
+
+    let mut tags = Vec::new();
+    for loaded in scenes.loaded() {
+        let mut entities = members.remove(&loaded.name).unwrap_or_default();
+        entities.sort_by_key(|(entity, id)| (id.is_none(), entity.id()));
+        // New entities take the indices after the scene's, the way loading numbers them
+        let mut next = entities.iter().filter_map(|(_, id)| *id).map(|id| (id & u32::MAX as u64) + 1).max().unwrap_or(0);
+        let mut ids = HashMap::new();
+        for (entity, id) in &entities {
+            let id = id.unwrap_or_else(|| {
+                let id = (1 << 32) | next;
+                next += 1;
+                tags.push((*entity, SceneMember { scene: loaded.name.clone(), id }));
+                id
+            });
+            ids.insert(entity.to_bits().get(), id);
+        }
+
+        let entities: Vec<Entity> = entities.into_iter().map(|(entity, _)| entity).collect();
+        let data = scene::save_entities(world, &entities, &ids, SceneFormat::from_path(&loaded.path));
+        if let Some(dir) = loaded.path.parent() && !dir.as_os_str().is_empty() {
+            std::fs::create_dir_all(dir).map_err(|err| format!("{}: {}", dir.display(), err))?;
+        }
+        std::fs::write(&loaded.path, data).map_err(|err| format!("{}: {}", loaded.path.display(), err))?;
+    }
+
+    for (entity, member) in tags {
+        let _ = world.insert_one(entity, member);
+    }
+    Ok(())
+}
+
+/// Writes the scenes in `assets/scenes` (and a root `Scene.bin`/`Scene.json`) into the build in
+/// `build_dir` as the shipped game loads them: binary, migrated and without prefab links, so it
+/// doesn't need the prefab files. Runs after the assets are copied and removes the text copies.
+pub fn ship_scenes(build_dir: &Path) -> Result<(), String> {
+    export_scenes(&build_dir.join(SCENES_DIR))?;
+    let src = if Path::new(scene::SCENE_TEXT).exists() { scene::SCENE_TEXT } else { scene::SCENE_BIN };
+    if Path::new(src).exists() {
+        scene::export_scene_file(src, build_dir.join(scene::SCENE_BIN))?;
+    }
+    Ok(())
+}
+
+fn export_scenes(dst: &Path) -> Result<(), String> {
+    for name in list() {
+        let src = scene_files(&name).into_iter().find(|path| path.exists()).unwrap();
+        std::fs::create_dir_all(dst).map_err(|err| format!("{}: {}", dst.display(), err))?;
+        scene::export_scene_file(&src, dst.join(format!("{}.bin", name)))?;
+        let text = dst.join(format!("{}.json", name));
+        if text.exists() {
+            std::fs::remove_file(&text).map_err(|err| format!("{}: {}", text.display(), err))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,336 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::SystemTime;
+use hecs::{Entity, World};
+use engine_macros::component;
+use serde::Deserialize;
+use crate::reflect;
+use crate::resources::Resource;
+use crate::systems::{GameSystem, Stage, SysCtx};
+
+pub const SCRIPTS_DIR: &str = "assets/scripts";
+
+/// How often script files are checked for changes, in seconds.
+const RELOAD_INTERVAL: f32 = 0.5;
+
+/// Optional script function returning the systems the script adds to the schedule, e.g.
+/// `[#{ run: "patrol", stage: "Update", after: ["player_control"] }]`. Each entry names a
+/// function taking `dt`, which runs once per entity with the script, and may set `stage`
+/// (`PreUpdate`, `Update` or `PostUpdate`), `fixed`, `while_paused`, `before` and `after` like
+/// `#[system]`. The system is called `<script path>::<function>` for ordering and the profiler.
+pub const SYSTEMS_FN: &str = "systems";
+/// Registered as an `Update` system when a script has no `systems()`. Inside it `this` is a map
+/// of the entity's components, e.g. `this.Pos.x += this.Vel.x * dt`; changes are written back.
+pub const UPDATE_HOOK: &str = "update";
+/// Same as `update`, but registered as a fixed-step system.
+pub const FIXED_UPDATE_HOOK: &str = "fixed_update";
+
+/// A Rhai script from `assets/scripts`, run by `ScriptHost`.
+#[component]
+pub struct Script { pub path: String }
+
+/// One entry of a script's `systems()`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    run: String,
+    #[serde(default = "default_stage")]
+    stage: String,
+    #[serde(default)]
+    fixed: bool,
+    #[serde(default)]
+    while_paused: bool,
+    #[serde(default)]
+    before: Vec<String>,
+    #[serde(default)]
+    after: Vec<String>,
+}
+
+fn default_stage() -> String {
+    "Update".to_string()
+}
+
+#[derive(PartialEq)]
+struct Registration {
+    run: String,
+    stage: Stage,
+    fixed: bool,
+    while_paused: bool,
+    before: Vec<String>,
+    after: Vec<String>,
+}
+
+struct LoadedScript {
+    ast: Option<rhai::AST>,
+    modified: Option<SystemTime>,
+    registrations: Vec<Registration>,
+    /// Last reported error, so a broken script doesn't flood the log every frame.
+    last_error: Option<String>,
+}
+
+impl LoadedScript {
+    fn report(&mut self, path: &str, error: Option<String>) {
+        if let Some(err) = &error
+            && self.last_error.as_ref() != Some(err)
+        {
+            println!("Script {}: {}", path, err);
+        }
+        self.last_error = error;
+    }
+}
+
+/// Compiles the scripts in `assets/scripts`, schedules the systems they register and reloads them when they change on disk.
+pub struct ScriptHost {
+    engine: rhai::Engine,
+    scripts: HashMap<String, LoadedScript>,
+    systems: Vec<&'static GameSystem>,
+    systems_changed: bool,
+    since_check: f32,
+}
+
+impl Resource for ScriptHost {}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Paths of every script under `dir`, relative to `SCRIPTS_DIR` and with `/` separators like `Script::path`.
+fn script_files(dir: &Path, prefix: &str, files: &mut Vec<String>) {
+    let Ok(entries) = std::fs::read_dir(dir) else { return };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        if path.is_dir() {
+            script_files(&path, &format!("{}/", name), files);
+        } else if path.extension().is_some_and(|ext| ext == "rhai") {
+            files.push(name);
+        }
+    }
+}
+
+fn compile(engine: &rhai::Engine, path: &str) -> (Option<SystemTime>, Result<rhai::AST, String>) {
+    let full_path = Path::new(SCRIPTS_DIR).join(path);
+    let modified = modified(&full_path);
+    let ast = std::fs::read_to_string(&full_path)
+        .map_err(|err| err.to_string())
+        .and_then(|source| engine.compile(source).map_err(|err| err.to_string()));
+    (modified, ast)
+}
+
+fn has_function(ast: &rhai::AST, name: &str, params: usize) -> bool {
+    ast.iter_functions().any(|f| f.name == name && f.params.len() == params)
+}
+
+/// Reads the systems a script registers, falling back to its `update`/`fixed_update` hooks.
+fn registrations(engine: &rhai::Engine, ast: &rhai::AST) -> Result<Vec<Registration>, String> {
+    if !has_function(ast, SYSTEMS_FN, 0) {
+        return Ok([(UPDATE_HOOK, false), (FIXED_UPDATE_HOOK, true)].into_iter()
+            .filter(|(hook, _)| has_function(ast, hook, 1))
+            .map(|(hook, fixed)| Registration {
+                run: hook.to_string(), stage: Stage::Update, fixed, while_paused: false, before: Vec::new(), after: Vec::new(),
+            })
+            .collect());
+    }
+
+    let options = rhai::CallFnOptions::new().eval_ast(false);
+    let entries: rhai::Array = engine.call_fn_with_options(options, &mut rhai::Scope::new(), ast, SYSTEMS_FN, ())
+        .map_err(|err| format!("{}(): {}", SYSTEMS_FN, err))?;
+
+    entries.iter().map(|entry| {
+        let entry: Entry = rhai::serde::from_dynamic(entry).map_err(|err| format!("{}(): {}", SYSTEMS_FN, err))?;
+        if !has_function(ast, &entry.run, 1) {
+            return Err(format!("{}(): `{}` isn't a function taking `dt`", SYSTEMS_FN, entry.run));
+        }
+        let stage = match entry.stage.as_str() {
+            "PreUpdate" => Stage::PreUpdate,
+            "Update" => Stage::Update,
+            "PostUpdate" => Stage::PostUpdate,
+            other => return Err(format!("{}(): `{}` can't run script systems, use PreUpdate, Update or PostUpdate", SYSTEMS_FN, other)),
+        };
+        Ok(Registration {
+            run: entry.run, stage, fixed: entry.fixed, while_paused: entry.while_paused, before: entry.before, after: entry.after,
+        })
+    }).collect()
+}
+
+fn leak(s: &str) -> &'static str {
+    Box::leak(s.to_string().into_boxed_str())
+}
+
+/// Builds the `GameSystem` for a registration. Names and lists are leaked like the ones of
+/// hot-reloaded libraries, since schedules keep `'static` references; it only happens on reload.
+fn script_system(path: &str, registration: &Registration) -> &'static GameSystem {
+    let names = |names: &[String]| -> &'static [&'static str] {
+        Box::leak(names.iter().map(|name| leak(name)).collect::<Vec<_>>().into_boxed_slice())
+    };
+    Box::leak(Box::new(GameSystem {
+        name: leak(&format!("{}::{}", path, registration.run)),
+        func: |_| {},
+        par_func: None,
+        setup: |_| {},
+        stage: registration.stage,
+        fixed: registration.fixed,
+        while_paused: registration.while_paused,
+        run_if: None,
+        state: None,
+        before: names(&registration.before),
+        after: names(&registration.after),
+        access: &[],
+        exclusive: true,
+        script: Some((leak(path), leak(&registration.run))),
+    }))
+}
+
+impl ScriptHost {
+    pub fn new() -> Self {
+        Self { engine: rhai::Engine::new(), scripts: HashMap::new(), systems: Vec::new(), systems_changed: false, since_check: 0.0 }
+    }
+
+    /// Compiles every script, so `systems` is complete before the schedule is first built.
+    pub fn load_all(&mut self) {
+        self.sync();
+        self.systems_changed = false;
+    }
+
+    /// Systems registered by the loaded scripts, to add to the schedule.
+    pub fn systems(&self) -> &[&'static GameSystem] {
+        &self.systems
+    }
+
+    /// Whether `systems` changed since the last call, so the schedule needs rebuilding.
+    pub fn take_systems_changed(&mut self) -> bool {
+        std::mem::take(&mut self.systems_changed)
+    }
+
+    /// Picks up new, changed and deleted scripts. A script that fails to compile keeps its previous version.
+    pub fn reload_changed(&mut self, dt: f32) {
+        self.since_check += dt;
+        if self.since_check < RELOAD_INTERVAL {
+            return;
+        }
+        self.since_check = 0.0;
+        self.sync();
+    }
+
+    fn sync(&mut self) {
+        let mut files = Vec::new();
+        script_files(Path::new(SCRIPTS_DIR), "", &mut files);
+
+        let count = self.scripts.len();
+        self.scripts.retain(|path, _| files.contains(path));
+        let mut changed = self.scripts.len() != count;
+
+        for path in files {
+            let current = modified(&Path::new(SCRIPTS_DIR).join(&path));
+            if self.scripts.get(&path).is_some_and(|script| script.modified == current) {
+                continue;
+            }
+            let (modified, ast) = compile(&self.engine, &path);
+            let reloaded = self.scripts.contains_key(&path);
+            let script = self.scripts.entry(path.clone()).or_insert_with(|| LoadedScript {
+                ast: None, modified, registrations: Vec::new(), last_error: None,
+            });
+            script.modified = modified;
+
+            match ast.and_then(|ast| registrations(&self.engine, &ast).map(|found| (ast, found))) {
+                Ok((ast, found)) => {
+                    changed |= script.registrations != found;
+                    script.ast = Some(ast);
+                    script.registrations = found;
+                    script.report(&path, None);
+                    if reloaded {
+                        println!("Reloaded script {}", path);
+                    }
+                }
+                Err(err) => script.report(&path, Some(err)),
+            }
+        }
+
+        if changed {
+            self.systems = self.scripts.iter()
+                .flat_map(|(path, script)| script.registrations.iter().map(|r| script_system(path, r)))
+                .collect();
+            self.systems_changed = true;
+        }
+    }
+
+    /// Calls `function(dt)` of the script at `path` for every entity that has it.
+    fn run(&mut self, world: &mut World, path: &str, function: &str, dt: f32) {
+        let Some(script) = self.scripts.get_mut(path) else { return };
+        let Some(ast) = script.ast.as_ref() else { return };
+
+        let scripted: Vec<Entity> = world.query::<&Script>().iter()
+            .filter(|(_, script)| script.path == path)
+            .map(|(entity, _)| entity)
+            .collect();
+
+        let mut error = None;
+        for entity in scripted {
+            if let Err(err) = call_hook(&self.engine, ast, world, entity, function, dt) {
+                error = Some(err);
+            }
+        }
+        script.report(path, error);
+    }
+}
+
+impl Default for ScriptHost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Runs a system registered by a script; called by the scheduler in place of `GameSystem::func`.
+pub(crate) fn run_system(ctx: &mut SysCtx, path: &str, function: &str) {
+    if let Some(mut scripts) = ctx.resources.get_mut::<ScriptHost>() {
+        scripts.run(ctx.world, path, function, ctx.dt);
+    }
+}
+
+/// Every component of `entity` as a script value, keyed by type name.
+fn components_to_script(world: &World, entity: Entity) -> rhai::Map {
+    let mut map = rhai::Map::new();
+    for info in reflect::components() {
+        match (info.to_script)(world, entity) {
+            Some(Ok(value)) => { map.insert(info.name.into(), value); }
+            Some(Err(err)) => println!("Failed to pass {} to a script: {}", info.name, err),
+            None => {}
+        }
+    }
+    map
+}
+
+fn call_hook(engine: &rhai::Engine, ast: &rhai::AST, world: &mut World, entity: Entity, hook: &str, dt: f32) -> Result<(), String> {
+    let before = components_to_script(world, entity);
+    let mut this = rhai::Dynamic::from_map(before.clone());
+
+    let options = rhai::CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut this);
+    let _: rhai::Dynamic = engine.call_fn_with_options(options, &mut rhai::Scope::new(), ast, hook, (dt as rhai::FLOAT,))
+        .map_err(|err| err.to_string())?;
+
+    let after = this.try_cast::<rhai::Map>().ok_or("`this` must stay a map of components")?;
+
+    // Only changed components are written back, so untouched ones keep runtime-only state like `Render::cached_sprite`.
+    // A component the script broke is skipped and reported; the others still apply.
+    let mut result = Ok(());
+    for (name, value) in &after {
+        let unchanged = before.get(name).is_some_and(|old| format!("{:?}", old) == format!("{:?}", value));
+        if unchanged {
+            continue;
+        }
+        let written = match reflect::component(name) {
+            Some(info) => (info.from_script)(world, entity, value),
+            None => Err(format!("unknown component `{}`", name)),
+        };
+        result = result.and(written);
+    }
+
+    let mut cmd = hecs::CommandBuffer::new();
+    for name in before.keys().filter(|name| !after.contains_key(*name)) {
+        if let Some(info) = reflect::component(name) {
+            (info.remove)(&mut cmd, entity);
+        }
+    }
+    cmd.run_on(world);
+    result
+}
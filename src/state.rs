@@ -0,0 +1,59 @@
+use crate::resources::Resource;
+use crate::systems::{Schedule, Stage, SysCtx, run_all_systems};
+
+/// The current high-level game state ("Menu", "Playing", "Paused", ...).
+/// Changes requested with `set` are queued and applied between frames by `apply_transition`,
+/// so every system in a frame sees the same state. The game picks the initial state, which is
+/// queued like any other so its `on_enter` systems run with the first `apply_transition`.
+pub struct GameState {
+    current: String,
+    queued: Option<String>,
+}
+
+impl Resource for GameState {}
+
+#[allow(dead_code)]
+impl GameState {
+    pub fn new(initial: &str) -> Self {
+        Self { current: String::new(), queued: Some(initial.to_string()) }
+    }
+
+    pub fn current(&self) -> &str {
+        &self.current
+    }
+
+    pub fn is(&self, state: &str) -> bool {
+        self.current == state
+    }
+
+    /// Queues a transition; the last request in a frame wins.
+    pub fn set(&mut self, state: &str) {
+        self.queued = Some(state.to_string());
+    }
+
+    pub fn queued(&self) -> Option<&str> {
+        self.queued.as_deref()
+    }
+}
+
+/// Stops hooks that keep re-queueing each other from hanging the frame.
+const MAX_CHAINED_TRANSITIONS: usize = 16;
+
+/// Runs `on_exit` systems of the old state, switches, then runs `on_enter` systems of the new one.
+/// Transitions queued by those hooks are applied in the same call.
+pub fn apply_transition(schedule: &Schedule, ctx: &mut SysCtx) {
+    for _ in 0..MAX_CHAINED_TRANSITIONS {
+        let next = match ctx.resources.get_mut::<GameState>() {
+            Some(mut state) => match state.queued.take() {
+                Some(next) if next != state.current => next,
+                _ => return,
+            },
+            None => return,
+        };
+
+        run_all_systems(schedule, Stage::OnExit, ctx);
+        ctx.resources.expect_mut::<GameState>().current = next;
+        run_all_systems(schedule, Stage::OnEnter, ctx);
+    }
+    println!("cargo:warning=More than {} chained state transitions, the rest are deferred to the next frame", MAX_CHAINED_TRANSITIONS);
+}
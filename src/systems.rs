@@ -1,23 +1,589 @@
 use inventory;
+use std::any::TypeId;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use crate::state::GameState;
+use crate::profiler::{Profiler, timed};
 
+// With hot reload the gameplay systems live in the `game_systems` library instead
+#[cfg(not(feature = "hot-reload"))]
 automod::dir!("src/systems");
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Stage {
+    PreUpdate,
+    Update,
+    PostUpdate,
+    Render,
+    /// After the first scene is loaded, and again after every `SceneManager::switch`.
+    Startup,
+    /// After every scene load, including the first one and additive ones.
+    OnSceneLoaded,
+    /// Once, before the window closes.
+    Shutdown,
+    /// When `GameState` switches to the system's `state`.
+    OnEnter,
+    /// When `GameState` switches away from the system's `state`.
+    OnExit,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 9] = [
+        Stage::PreUpdate, Stage::Update, Stage::PostUpdate, Stage::Render,
+        Stage::Startup, Stage::OnSceneLoaded, Stage::Shutdown, Stage::OnEnter, Stage::OnExit,
+    ];
+
+    /// Stages that run every frame and can therefore host `fixed` systems.
+    pub fn is_simulation(self) -> bool {
+        matches!(self, Stage::PreUpdate | Stage::Update | Stage::PostUpdate)
+    }
+}
+
+/// One component or resource a system borrows, as declared by its parameters.
+#[allow(dead_code)]
+pub struct Access {
+    pub name: &'static str,
+    pub type_id: fn() -> TypeId,
+    pub write: bool,
+}
+
 #[allow(dead_code)]
 pub struct GameSystem {
     pub name: &'static str,
     pub func: fn(&mut SysCtx),
+    /// Same system through shared borrows only, so it can run on a worker thread.
+    /// Only set for `#[system(parallel)]`, which can't be combined with `&mut` context or `Render`.
+    pub par_func: Option<fn(&SharedCtx)>,
+    /// Registers the event channels the system uses; must run before `par_func`.
+    pub setup: fn(&mut crate::events::Events),
+    pub stage: Stage,
+    pub fixed: bool,
+    /// Simulation-stage systems skip frames while the game is paused unless this is set.
+    pub while_paused: bool,
+    /// Checked on top of the pause rule above.
+    pub run_if: Option<fn(&SysCtx) -> bool>,
+    /// Only runs while `GameState` is in this state.
+    pub state: Option<&'static str>,
+    pub before: &'static [&'static str],
+    pub after: &'static [&'static str],
+    pub access: &'static [Access],
+    /// Takes `world`, `resources` or another context field that gives unrestricted access.
+    pub exclusive: bool,
+    /// Script path and function for systems registered by a script's `systems()`; `func` is unused for them.
+    pub script: Option<(&'static str, &'static str)>,
+}
+
+impl GameSystem {
+    /// Whether running both systems at the same time could alias a mutable borrow.
+    pub fn conflicts_with(&self, other: &GameSystem) -> bool {
+        if self.exclusive || other.exclusive {
+            return true;
+        }
+        self.access.iter().any(|a| {
+            other.access.iter().any(|b| (a.write || b.write) && (a.type_id)() == (b.type_id)())
+        })
+    }
 }
 
 inventory::collect!(GameSystem);
 
+/// Expanded by `#[system]` to register its `GameSystem`. The hot-reload library
+/// (`game_systems`) defines its own version that keeps systems out of the engine's registry.
+#[cfg_attr(feature = "hot-reload", allow(unused_macros))]
+macro_rules! register_system {
+    ($system:expr) => {
+        inventory::submit! { $system }
+    };
+}
+#[cfg_attr(feature = "hot-reload", allow(unused_imports))]
+pub(crate) use register_system;
+
 pub struct SysCtx<'a> {
     pub world: &'a mut hecs::World,
     pub sprites: &'a mut crate::sprite_manager::SpriteManager,
+    pub resources: &'a mut crate::resources::Resources,
+    pub events: &'a mut crate::events::Events,
+    pub commands: &'a mut Commands,
+    pub dt: f32,
+    /// How far rendering is between the previous and the current fixed step, in `0.0..1.0`.
+    pub alpha: f32,
+}
+
+impl SysCtx<'_> {
+    pub fn shared(&self) -> SharedCtx<'_> {
+        SharedCtx { world: self.world, resources: self.resources, events: self.events, dt: self.dt, alpha: self.alpha }
+    }
+}
+
+/// The part of `SysCtx` that can be shared between threads.
+pub struct SharedCtx<'a> {
+    pub world: &'a hecs::World,
+    pub resources: &'a crate::resources::Resources,
+    pub events: &'a crate::events::Events,
     pub dt: f32,
+    pub alpha: f32,
+}
+
+/// Deferred spawns, despawns and component changes, applied to the world at the end of each stage.
+pub type Commands = hecs::CommandBuffer;
+
+/// Engine flags for run conditions. Inserted as a resource by `main`.
+#[derive(Default)]
+pub struct RunState {
+    pub paused: bool,
+    pub editor_open: bool,
+}
+
+impl crate::resources::Resource for RunState {}
+
+/// Ready-made conditions for `#[system(run_if = ...)]`.
+#[allow(dead_code)]
+pub mod conditions {
+    use super::{RunState, SysCtx};
+    use crate::resources::Resource;
+
+    pub fn always(_ctx: &SysCtx) -> bool {
+        true
+    }
+
+    pub fn not_paused(ctx: &SysCtx) -> bool {
+        !ctx.resources.get::<RunState>().is_some_and(|s| s.paused)
+    }
+
+    pub fn paused(ctx: &SysCtx) -> bool {
+        !not_paused(ctx)
+    }
+
+    pub fn in_editor(ctx: &SysCtx) -> bool {
+        ctx.resources.get::<RunState>().is_some_and(|s| s.editor_open)
+    }
+
+    pub fn resource_exists<T: Resource>(ctx: &SysCtx) -> bool {
+        ctx.resources.contains::<T>()
+    }
+}
+
+/// Accumulates frame time and hands it out in fixed-size simulation steps.
+pub struct FixedTime {
+    pub hz: f32,
+    pub max_steps: u32,
+    accumulator: f32,
+}
+
+impl FixedTime {
+    pub fn new(hz: f32, max_steps: u32) -> Self {
+        Self { hz, max_steps, accumulator: 0.0 }
+    }
+
+    pub fn step_dt(&self) -> f32 {
+        1.0 / self.hz
+    }
+
+    /// Adds the frame time and returns how many fixed steps to run this frame.
+    /// Time beyond `max_steps` is dropped so a slow frame can't snowball into slower ones.
+    pub fn advance(&mut self, frame_dt: f32) -> u32 {
+        let step = self.step_dt();
+        self.accumulator += frame_dt;
+
+        let steps = (self.accumulator / step) as u32;
+        if steps > self.max_steps {
+            self.accumulator = 0.0;
+            return self.max_steps;
+        }
+        self.accumulator -= steps as f32 * step;
+        steps
+    }
+
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / self.step_dt()).clamp(0.0, 1.0)
+    }
+}
+
+/// A `#[system]` parameter that borrows the components in `Q` for the duration of the system.
+pub struct Query<'w, Q: hecs::Query> {
+    borrow: hecs::QueryBorrow<'w, Q>,
+}
+
+impl<'w, Q: hecs::Query> Query<'w, Q> {
+    pub fn new(world: &'w hecs::World) -> Self {
+        Self { borrow: world.query::<Q>() }
+    }
+
+    pub fn iter(&mut self) -> hecs::QueryIter<'_, Q> {
+        self.borrow.iter()
+    }
+
+    /// Random access to matching entities, e.g. to follow a target stored in a component.
+    pub fn view(&mut self) -> hecs::View<'_, Q> {
+        self.borrow.view()
+    }
+}
+
+impl<'q, Q: hecs::Query> IntoIterator for &'q mut Query<'_, Q> {
+    type Item = (hecs::Entity, Q::Item<'q>);
+    type IntoIter = hecs::QueryIter<'q, Q>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[derive(Debug)]
+pub enum ScheduleError {
+    DuplicateName(&'static str),
+    UnknownSystem { system: &'static str, target: &'static str },
+    CrossStage { system: &'static str, target: &'static str, stage: Stage, target_stage: Stage },
+    Cycle { stage: Stage, systems: Vec<&'static str> },
+    FixedOutsideSimulation { system: &'static str, stage: Stage },
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScheduleError::DuplicateName(name) => {
+                write!(f, "system `{}` is registered more than once", name)
+            }
+            ScheduleError::UnknownSystem { system, target } => {
+                write!(f, "system `{}` is ordered against `{}`, but no system with that name exists", system, target)
+            }
+            ScheduleError::CrossStage { system, target, stage, target_stage } => {
+                write!(f, "system `{}` ({:?}) is ordered against `{}` ({:?}), but ordering only applies within a stage", system, stage, target, target_stage)
+            }
+            ScheduleError::Cycle { stage, systems } => {
+                write!(f, "ordering cycle in stage {:?} between systems: {}", stage, systems.join(", "))
+            }
+            ScheduleError::FixedOutsideSimulation { system, stage } => {
+                write!(f, "system `{}` is marked `fixed`, but {:?} is not a simulation stage", system, stage)
+            }
+        }
+    }
 }
 
-pub fn run_all_systems(ctx: &mut SysCtx) {
-    for system in inventory::iter::<GameSystem> {
-        (system.func)(ctx);
+impl std::error::Error for ScheduleError {}
+
+/// Systems grouped by stage, sorted so that every `before`/`after` constraint holds.
+pub struct Schedule {
+    #[cfg_attr(not(debug_assertions), allow(dead_code))]
+    stages: HashMap<Stage, Vec<&'static GameSystem>>,
+    /// Per stage and `fixed` flag: consecutive groups of systems that may run concurrently.
+    batches: HashMap<(Stage, bool), Vec<Vec<&'static GameSystem>>>,
+    disabled: HashSet<&'static str>,
+    parallel: bool,
+}
+
+/// Splits sorted systems into batches. A system joins the current batch only if it can run
+/// on a worker thread, has no ordering constraint with the batch and doesn't conflict with it.
+/// Render systems always run alone on the main thread, since drawing only works there.
+fn build_batches(
+    sorted: &[&'static GameSystem],
+    edges: &HashMap<&'static str, Vec<&'static str>>,
+) -> Vec<Vec<&'static GameSystem>> {
+    let ordered = |a: &str, b: &str| {
+        edges.get(a).is_some_and(|to| to.contains(&b)) || edges.get(b).is_some_and(|to| to.contains(&a))
+    };
+
+    let mut batches: Vec<Vec<&'static GameSystem>> = Vec::new();
+    for &system in sorted {
+        let joins_last = system.par_func.is_some() && system.stage != Stage::Render && batches.last().is_some_and(|batch| {
+            batch.iter().all(|other| {
+                other.par_func.is_some() && !ordered(system.name, other.name) && !system.conflicts_with(other)
+            })
+        });
+        match batches.last_mut() {
+            Some(batch) if joins_last => batch.push(system),
+            _ => batches.push(vec![system]),
+        }
+    }
+    batches
+}
+
+impl Schedule {
+    pub fn build() -> Result<Self, ScheduleError> {
+        Self::build_with(&[])
+    }
+
+    /// Builds from the registered systems plus `extra`, e.g. the ones loaded by `hot_reload`.
+    pub fn build_with(extra: &[&'static GameSystem]) -> Result<Self, ScheduleError> {
+        let mut by_name: HashMap<&'static str, &'static GameSystem> = HashMap::new();
+        for system in inventory::iter::<GameSystem>.into_iter().chain(extra.iter().copied()) {
+            if by_name.insert(system.name, system).is_some() {
+                return Err(ScheduleError::DuplicateName(system.name));
+            }
+            if system.fixed && !system.stage.is_simulation() {
+                return Err(ScheduleError::FixedOutsideSimulation { system: system.name, stage: system.stage });
+            }
+        }
+
+        // edges[a] contains b when a must run before b
+        let mut edges: HashMap<&'static str, Vec<&'static str>> = HashMap::new();
+        for system in by_name.values() {
+            let targets = system.before.iter().map(|t| (system.name, *t, true))
+                .chain(system.after.iter().map(|t| (system.name, *t, false)));
+
+            for (name, target, is_before) in targets {
+                let Some(other) = by_name.get(target) else {
+                    return Err(ScheduleError::UnknownSystem { system: name, target });
+                };
+                if other.stage != system.stage {
+                    return Err(ScheduleError::CrossStage { system: name, target, stage: system.stage, target_stage: other.stage });
+                }
+                let (from, to) = if is_before { (name, target) } else { (target, name) };
+                edges.entry(from).or_default().push(to);
+            }
+        }
+
+        let mut stages = HashMap::new();
+        let mut batches = HashMap::new();
+        for stage in Stage::ALL {
+            let mut names: Vec<&'static str> = by_name.values()
+                .filter(|s| s.stage == stage)
+                .map(|s| s.name)
+                .collect();
+            names.sort();
+
+            let mut in_degree: HashMap<&'static str, usize> = names.iter().map(|n| (*n, 0)).collect();
+            for name in &names {
+                for to in edges.get(name).into_iter().flatten() {
+                    *in_degree.get_mut(to).unwrap() += 1;
+                }
+            }
+
+            // Kahn's algorithm; ready systems are taken alphabetically so the order is stable across builds
+            let mut sorted = Vec::with_capacity(names.len());
+            let mut ready: Vec<&'static str> = names.iter().copied().filter(|n| in_degree[n] == 0).collect();
+            while let Some(name) = ready.first().copied() {
+                ready.remove(0);
+                sorted.push(by_name[name]);
+                for to in edges.get(name).into_iter().flatten() {
+                    let degree = in_degree.get_mut(to).unwrap();
+                    *degree -= 1;
+                    if *degree == 0 {
+                        let idx = ready.binary_search(to).unwrap_or_else(|i| i);
+                        ready.insert(idx, to);
+                    }
+                }
+            }
+
+            if sorted.len() != names.len() {
+                let systems = names.into_iter().filter(|n| in_degree[n] > 0).collect();
+                return Err(ScheduleError::Cycle { stage, systems });
+            }
+
+            for fixed in [false, true] {
+                let group: Vec<_> = sorted.iter().copied().filter(|s| s.fixed == fixed).collect();
+                batches.insert((stage, fixed), build_batches(&group, &edges));
+            }
+            stages.insert(stage, sorted);
+        }
+
+        Ok(Self { stages, batches, disabled: HashSet::new(), parallel: false })
+    }
+
+    /// Carries the editor toggles over to a rebuilt schedule.
+    pub fn keep_settings(&mut self, old: &Schedule) {
+        self.disabled = old.disabled.clone();
+        self.parallel = old.parallel;
+    }
+
+    #[cfg_attr(not(debug_assertions), allow(dead_code))]
+    pub fn systems(&self, stage: Stage) -> &[&'static GameSystem] {
+        self.stages.get(&stage).map(|s| s.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        !self.disabled.contains(name)
+    }
+
+    /// Switches a system off (or back on) without touching its code, e.g. from the editor.
+    #[allow(dead_code)]
+    pub fn set_enabled(&mut self, name: &'static str, enabled: bool) {
+        if enabled {
+            self.disabled.remove(name);
+        } else {
+            self.disabled.insert(name);
+        }
+    }
+
+    #[cfg_attr(not(debug_assertions), allow(dead_code))]
+    pub fn is_parallel(&self) -> bool {
+        self.parallel
+    }
+
+    /// Runs non-conflicting systems on worker threads. Always single-threaded on wasm32.
+    #[cfg_attr(not(debug_assertions), allow(dead_code))]
+    pub fn set_parallel(&mut self, parallel: bool) {
+        self.parallel = parallel && cfg!(not(target_arch = "wasm32"));
+    }
+
+    fn should_run(&self, system: &GameSystem, ctx: &SysCtx) -> bool {
+        if !self.is_enabled(system.name) {
+            return false;
+        }
+        if let Some(state) = system.state
+            && !ctx.resources.get::<GameState>().is_some_and(|s| s.is(state))
+        {
+            return false;
+        }
+        let runs_now = system.while_paused || !system.stage.is_simulation() || conditions::not_paused(ctx);
+        runs_now && system.run_if.is_none_or(|condition| condition(ctx))
+    }
+}
+
+fn run_system(system: &GameSystem, ctx: &mut SysCtx) {
+    let ((), ms) = timed(|| match system.script {
+        Some((path, function)) => crate::scripting::run_system(ctx, path, function),
+        None => (system.func)(ctx),
+    });
+    if let Some(mut profiler) = ctx.resources.get_mut::<Profiler>() {
+        profiler.record(system.name, ms);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn run_batch(batch: &[&'static GameSystem], ctx: &mut SysCtx) {
+    for system in batch {
+        (system.setup)(ctx.events);
+    }
+
+    // Rayon's pool keeps its worker threads between batches, so a batch costs no thread spawns.
+    // Workers time themselves with `Instant`, since macroquad's clock belongs to the main thread.
+    let shared = ctx.shared();
+    let mut timings: Vec<(&'static str, f32)> = vec![("", 0.0); batch.len()];
+    rayon::scope(|scope| {
+        for (system, timing) in batch.iter().zip(timings.iter_mut()) {
+            let func = system.par_func.expect("batched systems always have a par_func");
+            let shared = &shared;
+            scope.spawn(move |_| {
+                let start = std::time::Instant::now();
+                func(shared);
+                *timing = (system.name, start.elapsed().as_secs_f32() * 1000.0);
+            });
+        }
+    });
+
+    if let Some(mut profiler) = ctx.resources.get_mut::<Profiler>() {
+        for (name, ms) in timings {
+            profiler.record(name, ms);
+        }
+    }
+}
+
+fn run_stage(schedule: &Schedule, stage: Stage, fixed: bool, ctx: &mut SysCtx) {
+    ctx.events.set_fixed_step(fixed);
+    for batch in schedule.batches.get(&(stage, fixed)).into_iter().flatten() {
+        // Conditions are checked right before the batch so they see the effects of earlier ones
+        let ready: Vec<&'static GameSystem> = batch.iter().copied().filter(|s| schedule.should_run(s, ctx)).collect();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if schedule.parallel && ready.len() > 1 {
+            run_batch(&ready, ctx);
+            continue;
+        }
+
+        for system in ready {
+            run_system(system, ctx);
+        }
+    }
+    ctx.events.set_fixed_step(false);
+    ctx.commands.run_on(ctx.world);
+}
+
+/// Runs the frame-rate systems of `stage`, then applies the commands they queued.
+pub fn run_all_systems(schedule: &Schedule, stage: Stage, ctx: &mut SysCtx) {
+    run_stage(schedule, stage, false, ctx);
+}
+
+/// Runs the `#[system(fixed)]` systems of `stage`; `ctx.dt` is expected to be the fixed step.
+pub fn run_fixed_systems(schedule: &Schedule, stage: Stage, ctx: &mut SysCtx) {
+    run_stage(schedule, stage, true, ctx);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn system(name: &'static str, before: &'static [&'static str], after: &'static [&'static str]) -> &'static GameSystem {
+        Box::leak(Box::new(GameSystem {
+            name,
+            func: |_| {},
+            par_func: None,
+            setup: |_| {},
+            stage: Stage::Render,
+            fixed: false,
+            while_paused: false,
+            run_if: None,
+            state: None,
+            before,
+            after,
+            access: &[],
+            exclusive: false,
+            script: None,
+        }))
+    }
+
+    fn order(schedule: &Schedule) -> Vec<&'static str> {
+        schedule.systems(Stage::Render).iter().map(|s| s.name).filter(|n| n.starts_with("test_")).collect()
+    }
+
+    #[test]
+    fn before_and_after_override_alphabetical_order() {
+        let extra = [
+            system("test_a", &[], &[]),
+            system("test_b", &[], &["test_c"]),
+            system("test_c", &["test_a"], &[]),
+        ];
+        let schedule = Schedule::build_with(&extra).unwrap();
+        assert_eq!(order(&schedule), ["test_c", "test_a", "test_b"]);
+    }
+
+    #[test]
+    fn unconstrained_systems_are_alphabetical() {
+        let extra = [system("test_z", &[], &[]), system("test_m", &[], &[]), system("test_a", &[], &[])];
+        let schedule = Schedule::build_with(&extra).unwrap();
+        assert_eq!(order(&schedule), ["test_a", "test_m", "test_z"]);
+    }
+
+    #[test]
+    fn cycles_are_reported_with_their_systems() {
+        let extra = [
+            system("test_a", &["test_b"], &[]),
+            system("test_b", &["test_c"], &[]),
+            system("test_c", &["test_a"], &[]),
+            system("test_d", &[], &[]),
+        ];
+        match Schedule::build_with(&extra) {
+            Err(ScheduleError::Cycle { stage, systems }) => {
+                assert_eq!(stage, Stage::Render);
+                assert_eq!(systems, ["test_a", "test_b", "test_c"]);
+            }
+            other => panic!("expected a cycle, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn unknown_targets_are_reported() {
+        let extra = [system("test_a", &["test_missing"], &[])];
+        assert!(matches!(
+            Schedule::build_with(&extra),
+            Err(ScheduleError::UnknownSystem { system: "test_a", target: "test_missing" })
+        ));
+    }
+
+    #[test]
+    fn fixed_time_carries_leftover_time_into_the_next_frame() {
+        let mut time = FixedTime::new(10.0, 5);
+        assert_eq!(time.advance(0.25), 2);
+        assert!((time.alpha() - 0.5).abs() < 1e-4);
+        assert_eq!(time.advance(0.06), 1);
+        assert!((time.alpha() - 0.1).abs() < 1e-4);
+        assert_eq!(time.advance(0.0), 0);
+    }
+
+    #[test]
+    fn fixed_time_drops_time_beyond_max_steps() {
+        let mut time = FixedTime::new(10.0, 3);
+        assert_eq!(time.advance(1.0), 3);
+        assert_eq!(time.alpha(), 0.0);
+        assert_eq!(time.advance(0.1), 1);
+    }
+}
@@ -1,8 +1,8 @@
 use crate::en::*;
 
-#[system]
-fn player_control(world: &mut World) {
-    for (_id, (vel,ren ,player)) in world.query_mut::<(&mut Vel,&mut Render, &Player)>() {
+#[system(fixed)]
+fn player_control(mut q: Query<(&mut Vel, &mut Render, &Player)>) {
+    for (_id, (vel, ren, player)) in &mut q {
         let mut x_move: f32 = 0.0;
         let mut y_move: f32 = 0.0;
         let speed = player.speed;
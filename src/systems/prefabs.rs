@@ -0,0 +1,7 @@
+use crate::en::*;
+use crate::prefab;
+
+#[system(stage = PreUpdate, while_paused)]
+fn reload_prefabs(world: &mut World, dt: f32) {
+    prefab::reload_changed(world, dt);
+}
@@ -0,0 +1,8 @@
+use crate::en::*;
+
+#[system(on_scene_loaded)]
+fn reset_sprite_cache(mut q: Query<&mut Render>) {
+    for (_id, ren) in &mut q {
+        ren.cached_sprite = None;
+    }
+}
@@ -0,0 +1,6 @@
+use crate::en::*;
+
+#[system(stage = PreUpdate, while_paused)]
+fn reload_scripts(mut scripts: ResMut<ScriptHost>, dt: f32) {
+    scripts.reload_changed(dt);
+}